@@ -1,13 +1,27 @@
 #include "./MyWrapper.h"
+#include <cstring>
+#include <memory>
 
 
 void MyWrapper::init(const std::string& config_path){
-    YAML::Node config = Ramulator::Config::parse_config_file(config_path, {});
-    ramulator2_frontend = Ramulator::Factory::create_frontend(config);
-    ramulator2_memorysystem = Ramulator::Factory::create_memory_system(config);
+    setup(Ramulator::Config::parse_config_file(config_path, {}));
+}
+
+// The config is already complete, so there are no includes to resolve.
+void MyWrapper::init_from_string(const std::string& config_yaml){
+    setup(YAML::Load(config_yaml));
+}
 
-    ramulator2_frontend->connect_memory_system(ramulator2_memorysystem);
-    ramulator2_memorysystem->connect_frontend(ramulator2_frontend);
+// Nothing is kept unless both halves are created, so a config that fails
+// halfway leaves the wrapper uninitialised rather than half built.
+void MyWrapper::setup(YAML::Node config){
+    std::unique_ptr<Ramulator::IFrontEnd> frontend(Ramulator::Factory::create_frontend(config));
+    std::unique_ptr<Ramulator::IMemorySystem> memory_system(Ramulator::Factory::create_memory_system(config));
+
+    frontend->connect_memory_system(memory_system.get());
+    memory_system->connect_frontend(frontend.get());
+    ramulator2_frontend = frontend.release();
+    ramulator2_memorysystem = memory_system.release();
 }
 
 float MyWrapper::get_memory_tCK() const {
@@ -16,9 +30,19 @@ float MyWrapper::get_memory_tCK() const {
 
 //void response(Packet pkt, int64_t latency);
 
-bool MyWrapper::send_request(int64_t addr, bool is_write, std::function<void(Ramulator::Request&)> callback) {
+// The request types send_request can deliver, in type id order. A spec's
+// m_requests lists more (DDR4 has refreshes and row opens and closes), but
+// those are the controller's own: the GEM5 frontend's
+// receive_external_requests only turns reads and writes into requests for
+// the generic controller, and the memory system interface does not expose
+// the spec anyway (get_supported_requests is commented out).
+std::vector<std::string> MyWrapper::request_types() const {
+    return {"read", "write"};
+}
+
+bool MyWrapper::send_request(int64_t addr, int type_id, std::function<void(Ramulator::Request&)> callback) {
     bool enqueue_success;
-    enqueue_success = ramulator2_frontend->receive_external_requests(is_write, addr, 0, callback);
+    enqueue_success = ramulator2_frontend->receive_external_requests(type_id, addr, 0, callback);
     // if (enqueue_success) {
     //     // only the write success will go inside it.
     //     if (pkt.is_write){
@@ -59,11 +83,60 @@ MyWrapper::~MyWrapper() {
     }
 }
 
+static int report(char* err, size_t err_len, int status, const char* msg) {
+    if (err && err_len > 0) {
+        std::strncpy(err, msg, err_len - 1);
+        err[err_len - 1] = '\0';
+    }
+    return status;
+}
+
+// The factory and its children throw ConfigurationError for every config
+// problem; only the message tells a missing implementation apart.
+static int classify(const Ramulator::ConfigurationError& e) {
+    std::string msg = e.what();
+    if (msg.find("not registered") != std::string::npos
+        || msg.find("No implementation specified") != std::string::npos
+        || msg.find("different from the desired") != std::string::npos) {
+        return DRAM_UNKNOWN_IMPL;
+    }
+    return DRAM_CONFIG_PARSE;
+}
+
+// Run `body` and turn any exception into a status code, as one unwinding
+// through extern "C" would abort the Rust process.
+template <typename F>
+static int guarded(char* err, size_t err_len, F&& body) {
+    try {
+        return body();
+    } catch (const YAML::Exception& e) {
+        return report(err, err_len, DRAM_CONFIG_PARSE, e.what());
+    } catch (const Ramulator::ConfigurationError& e) {
+        return report(err, err_len, classify(e), e.what());
+    } catch (const std::exception& e) {
+        return report(err, err_len, DRAM_INTERNAL, e.what());
+    } catch (...) {
+        return report(err, err_len, DRAM_INTERNAL, "unknown exception");
+    }
+}
+
+static int not_initialized(char* err, size_t err_len) {
+    return report(err, err_len, DRAM_NOT_INITIALIZED, "the memory system is not initialised");
+}
+
 extern "C" {
 
-    // Factory: create a new MyWrapper instance
+    int dram_abi_version() {
+        return DRAM_ABI_VERSION;
+    }
+
+    // Factory: create a new MyWrapper instance, null if allocation fails
     MyWrapper* dram_new() {
-        return new MyWrapper();
+        try {
+            return new MyWrapper();
+        } catch (...) {
+            return nullptr;
+        }
     }
     
     // Destructor: delete a MyWrapper instance
@@ -72,35 +145,94 @@ extern "C" {
     }
     
     // Wrap init method: pass config path as C string
-    void dram_init(MyWrapper* obj, const char* config_path) {
-        obj->init(std::string(config_path));
+    int dram_init(MyWrapper* obj, const char* config_path, char* err, size_t err_len) {
+        return guarded(err, err_len, [&] {
+            obj->init(std::string(config_path));
+            return DRAM_OK;
+        });
+    }
+    
+    // Wrap init_from_string method: pass the YAML text itself
+    int dram_init_from_string(MyWrapper* obj, const char* config_yaml, char* err, size_t err_len) {
+        return guarded(err, err_len, [&] {
+            obj->init_from_string(std::string(config_yaml));
+            return DRAM_OK;
+        });
     }
     
     // Wrap get_memory_tCK method
-    float get_memory_tCK(MyWrapper* obj) {
-        return obj->get_memory_tCK();
+    int get_memory_tCK(MyWrapper* obj, float* tck, char* err, size_t err_len) {
+        if (!obj->initialized()) {
+            return not_initialized(err, err_len);
+        }
+        return guarded(err, err_len, [&] {
+            *tck = obj->get_memory_tCK();
+            return DRAM_OK;
+        });
     }
     
     // Wrap send_request method
-    bool send_request(MyWrapper* obj, int64_t addr, bool is_write, void (*callback)(Ramulator::Request*, void*), void* ctx) {
-        return obj->send_request(addr, is_write, 
-            [callback, ctx](Ramulator::Request& req) {
-                callback(&req, ctx);
-            });
+    int send_request(MyWrapper* obj, int64_t addr, int type_id, void (*callback)(Ramulator::Request*, void*), void* ctx, char* err, size_t err_len) {
+        if (!obj->initialized()) {
+            return not_initialized(err, err_len);
+        }
+        return guarded(err, err_len, [&] {
+            bool accepted = obj->send_request(addr, type_id,
+                [callback, ctx](Ramulator::Request& req) {
+                    callback(&req, ctx);
+                });
+            return accepted ? DRAM_OK : report(err, err_len, DRAM_QUEUE_FULL, "request queue full");
+        });
+    }
+    
+    // The request types as a comma-separated list, in type id order
+    int dram_request_types(MyWrapper* obj, char* types, size_t types_len, char* err, size_t err_len) {
+        if (!obj->initialized()) {
+            return not_initialized(err, err_len);
+        }
+        return guarded(err, err_len, [&]() -> int {
+            std::string list;
+            for (const auto& name : obj->request_types()) {
+                list += list.empty() ? name : "," + name;
+            }
+            if (list.size() >= types_len) {
+                return report(err, err_len, DRAM_INTERNAL, "request type list does not fit the buffer");
+            }
+            std::strcpy(types, list.c_str());
+            return DRAM_OK;
+        });
     }
     
     // Wrap finish method
-    void MyWrapper_finish(MyWrapper* obj) {
-        obj->finish();
+    int MyWrapper_finish(MyWrapper* obj, char* err, size_t err_len) {
+        if (!obj->initialized()) {
+            return not_initialized(err, err_len);
+        }
+        return guarded(err, err_len, [&] {
+            obj->finish();
+            return DRAM_OK;
+        });
     }
     
     // Wrap tick method
-    void frontend_tick(MyWrapper* obj) {
-        obj->frontend_tick();
+    int frontend_tick(MyWrapper* obj, char* err, size_t err_len) {
+        if (!obj->initialized()) {
+            return not_initialized(err, err_len);
+        }
+        return guarded(err, err_len, [&] {
+            obj->frontend_tick();
+            return DRAM_OK;
+        });
     }
 
-    void memory_system_tick(MyWrapper* obj) {
-        obj->memory_system_tick();
+    int memory_system_tick(MyWrapper* obj, char* err, size_t err_len) {
+        if (!obj->initialized()) {
+            return not_initialized(err, err_len);
+        }
+        return guarded(err, err_len, [&] {
+            obj->memory_system_tick();
+            return DRAM_OK;
+        });
     }
     
-}
\ No newline at end of file
+}
@@ -6,9 +6,24 @@
 #include "include/memory_system.h"
 #include "include/base.h"
 #include "include/request.h"
+#include <cstddef>
 #include <deque>
 #include <unordered_map>
 
+// Status codes of the extern "C" entry points, mirrored by MemoryError on
+// the Rust side. A failing call also writes a message into the caller's
+// buffer. Bump DRAM_ABI_VERSION whenever the C signatures change.
+#define DRAM_ABI_VERSION 3
+
+enum DramStatus {
+    DRAM_OK = 0,
+    DRAM_CONFIG_PARSE = 1,     // unreadable or malformed config
+    DRAM_UNKNOWN_IMPL = 2,     // the config names an unregistered implementation
+    DRAM_NOT_INITIALIZED = 3,  // used before a successful init
+    DRAM_QUEUE_FULL = 4,       // the frontend refused the request
+    DRAM_INTERNAL = 5,         // any other exception
+};
+
 // struct Packet {
 //     bool is_write; // 0 means read, 1 means write
 //     int64_t addr;
@@ -20,18 +35,29 @@ class MyWrapper {
 public:
     MyWrapper() = default;
     ~MyWrapper();
+    // it owns the Ramulator objects, a copy would delete them twice
+    MyWrapper(const MyWrapper&) = delete;
+    MyWrapper& operator=(const MyWrapper&) = delete;
     void init(const std::string& config_path);
+    void init_from_string(const std::string& config_yaml);
     float get_memory_tCK() const;
-    bool send_request(int64_t addr, bool is_write, std::function<void(Ramulator::Request&)> callback);
+    // type_id indexes request_types(), 0 and 1 are always read and write
+    bool send_request(int64_t addr, int type_id, std::function<void(Ramulator::Request&)> callback);
+    std::vector<std::string> request_types() const;
     void finish();
     void frontend_tick();
     void memory_system_tick();
 
+    bool initialized() const { return ramulator2_memorysystem != nullptr; }
+
     std::string config_path;
-    Ramulator::IFrontEnd* ramulator2_frontend;
-    Ramulator::IMemorySystem* ramulator2_memorysystem;
+    Ramulator::IFrontEnd* ramulator2_frontend = nullptr;
+    Ramulator::IMemorySystem* ramulator2_memorysystem = nullptr;
 
     //std::unordered_map<int64_t, Packet> memory;
+
+private:
+    void setup(YAML::Node config);
 };
 
 #endif // MYWRAPPER_H
@@ -0,0 +1,129 @@
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Compiler and linker flags for Ramulator: RAMULATOR_DIR with include/ and
+// lib/ below it, else whatever pkg-config knows as ramulator.
+fn find_ramulator() -> Option<(Vec<String>, Vec<String>)> {
+    if let Ok(dir) = env::var("RAMULATOR_DIR") {
+        let dir = PathBuf::from(dir);
+        let lib = dir.join("lib");
+        if !lib.join("libramulator.so").exists() {
+            println!("cargo:warning=RAMULATOR_DIR has no lib/libramulator.so");
+            return None;
+        }
+        let cflags = vec![format!("-I{}", dir.join("include").display())];
+        let libs = vec![format!("-L{}", lib.display()), "-lramulator".to_string()];
+        return Some((cflags, libs));
+    }
+    let output = Command::new("pkg-config")
+        .args(["--cflags", "--libs", "ramulator"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let flags = String::from_utf8_lossy(&output.stdout);
+    let (libs, cflags) = flags
+        .split_whitespace()
+        .map(str::to_string)
+        .partition(|f| f.starts_with("-L") || f.starts_with("-l"));
+    Some((cflags, libs))
+}
+
+fn compile(cxx: &str, args: &[String]) -> bool {
+    Command::new(cxx)
+        .args(args)
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn main() {
+    let manifest = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let root = manifest.parent().unwrap();
+    let source = root.join("MyWrapper.cpp");
+    println!("cargo:rerun-if-env-changed=RAMULATOR_DIR");
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG_PATH");
+    println!("cargo:rerun-if-env-changed=CXX");
+    println!("cargo:rerun-if-changed={}", source.display());
+    println!("cargo:rerun-if-changed={}", root.join("MyWrapper.h").display());
+    println!("cargo:rerun-if-changed={}", root.join("include").display());
+
+    let dynamic = env::var_os("CARGO_FEATURE_RAMULATOR").is_some();
+    let linked = env::var_os("CARGO_FEATURE_RAMULATOR_STATIC").is_some();
+    if !dynamic && !linked {
+        return;
+    }
+    let Some((cflags, libs)) = find_ramulator() else {
+        // the static backend cannot link without it, the dynamic one just
+        // has nothing to load
+        if linked {
+            panic!("ramulator-static needs Ramulator: set RAMULATOR_DIR or provide ramulator.pc");
+        }
+        println!(
+            "cargo:warning=Ramulator not found (set RAMULATOR_DIR or provide ramulator.pc), \
+             the ramulator backend has no shim to load"
+        );
+        return;
+    };
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let cxx = env::var("CXX").unwrap_or_else(|_| "c++".to_string());
+    let mut args: Vec<String> = ["-std=c++20", "-O2", "-fPIC"].map(str::to_string).to_vec();
+    args.push(format!("-I{}", root.display()));
+    args.extend(cflags);
+    args.push(source.display().to_string());
+
+    if dynamic {
+        // let the loaded shim find libramulator without LD_LIBRARY_PATH
+        let rpaths: Vec<String> = libs
+            .iter()
+            .filter_map(|f| f.strip_prefix("-L"))
+            .map(|dir| format!("-Wl,-rpath,{}", dir))
+            .collect();
+        let lib = out.join("libwrapper.so");
+        let mut shared = args.clone();
+        shared.extend(["-shared".to_string(), "-o".to_string(), lib.display().to_string()]);
+        shared.extend(libs.iter().cloned());
+        shared.extend(rpaths);
+        if compile(&cxx, &shared) {
+            println!("cargo:rustc-env=MEMORY_INTERFACE_BUILT_LIB={}", lib.display());
+        } else {
+            println!(
+                "cargo:warning=failed to compile {} with {}, the ramulator backend has no shim to load",
+                source.display(),
+                cxx
+            );
+        }
+    }
+
+    if linked {
+        // libramulator itself stays a shared library, found through the
+        // system paths or LD_LIBRARY_PATH when the binary runs
+        let object = out.join("MyWrapper.o");
+        let mut object_args = args;
+        object_args.extend(["-c".to_string(), "-o".to_string(), object.display().to_string()]);
+        if !compile(&cxx, &object_args) {
+            panic!("failed to compile {} with {}", source.display(), cxx);
+        }
+        let archived = Command::new("ar")
+            .arg("crs")
+            .arg(out.join("libwrapper.a"))
+            .arg(&object)
+            .status()
+            .is_ok_and(|status| status.success());
+        if !archived {
+            panic!("failed to archive {}", object.display());
+        }
+        println!("cargo:rustc-link-search=native={}", out.display());
+        println!("cargo:rustc-link-lib=static=wrapper");
+        for flag in libs.iter() {
+            if let Some(dir) = flag.strip_prefix("-L") {
+                println!("cargo:rustc-link-search=native={}", dir);
+            } else if let Some(name) = flag.strip_prefix("-l") {
+                println!("cargo:rustc-link-lib=dylib={}", name);
+            }
+        }
+        println!("cargo:rustc-link-lib=dylib=stdc++");
+    }
+}
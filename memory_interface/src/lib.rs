@@ -0,0 +1,828 @@
+//! Bindings to the C shim around Ramulator 2 in MyWrapper.cpp, shared by
+//! the simulator and the wrapper smoke test. Without the shim, a built-in
+//! fixed-latency model stands in for the DRAM.
+//!
+//! Each backend is behind a cargo feature: `ramulator` loads libwrapper.so
+//! at run time, `ramulator-static` links the shim into the binary and
+//! `mock` is the built-in model. [`BackendKind`] picks among those compiled in.
+//!
+//! Any backend can log its traffic to a [`trace`] for offline analysis.
+
+#[cfg(not(any(feature = "ramulator", feature = "ramulator-static", feature = "mock")))]
+compile_error!("enable at least one of the ramulator, ramulator-static and mock features");
+
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(any(feature = "ramulator", feature = "ramulator-static"))]
+mod shim;
+pub mod trace;
+
+#[cfg(feature = "mock")]
+use mock::MockDram;
+#[cfg(any(feature = "ramulator", feature = "ramulator-static"))]
+use shim::Wrapper;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::error::Error;
+use std::ffi::{c_int, c_void};
+use std::ptr;
+use std::fmt;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+#[cfg(feature = "ramulator")]
+use std::collections::HashMap;
+#[cfg(feature = "ramulator")]
+use std::sync::OnceLock;
+use std::sync::{Arc, Mutex};
+use trace::{TraceEvent, TraceRecord, TraceWriter};
+
+// The shim to load: MEMORY_INTERFACE_LIB if set, else the one build.rs
+// compiled, if it found Ramulator.
+pub fn library_path() -> Option<String> {
+    env::var("MEMORY_INTERFACE_LIB")
+        .ok()
+        .or_else(|| option_env!("MEMORY_INTERFACE_BUILT_LIB").map(str::to_string))
+}
+
+#[repr(C)]
+pub struct Request {
+    pub addr: i64,
+    pub addr_vec: Vec<i32>,
+    pub type_id: i32,
+    pub source_id: i32,
+    pub command: i32,
+    pub final_command: i32,
+    pub is_stat_updated: bool,
+    pub arrive: i64,
+    pub depart: i64,
+    pub scratchpad: [i32; 4],
+    pub callback: Option<extern "C" fn(*mut Request)>,
+    pub m_payload: *mut c_void,
+}
+
+// Called with a finished read and the ctx it was sent with. A panic in it
+// is caught before it can reach the backend and returned from memory_tick.
+pub type RequestCallback = fn(&Request, *mut c_void);
+
+// What a request asks of the memory. Beyond reads and writes it is up to
+// the backend which of these it takes; ask the interface what it supports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestKind {
+    Read,
+    Write,
+    Refresh,  // an all-bank refresh
+    OpenRow,  // activate the row without moving data
+    CloseRow, // precharge its bank
+}
+
+impl RequestKind {
+    pub const ALL: [RequestKind; 5] = [
+        RequestKind::Read,
+        RequestKind::Write,
+        RequestKind::Refresh,
+        RequestKind::OpenRow,
+        RequestKind::CloseRow,
+    ];
+
+    // As the Ramulator specs name them.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RequestKind::Read => "read",
+            RequestKind::Write => "write",
+            RequestKind::Refresh => "all-bank-refresh",
+            RequestKind::OpenRow => "open-row",
+            RequestKind::CloseRow => "close-row",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        RequestKind::ALL.into_iter().find(|kind| kind.name() == s)
+    }
+}
+
+impl fmt::Display for RequestKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// What the backends call, see `trampoline`.
+type RawCallback = extern "C" fn(*mut Request, *mut c_void);
+
+// What the interface shares with the reads in flight: where to leave a panic
+// their callbacks raise, and the trace they are logged to.
+#[derive(Default)]
+struct Shared {
+    panicked: RefCell<Option<Box<dyn Any + Send>>>,
+    trace: RefCell<Option<TraceWriter>>,
+    stamp: Cell<u64>,
+    cycle: Cell<u64>,
+}
+
+impl Shared {
+    fn log(&self, event: TraceEvent, addr: i64, kind: RequestKind, id: u64, latency: u32) {
+        let mut trace = self.trace.borrow_mut();
+        let Some(writer) = trace.as_mut() else {
+            return;
+        };
+        let record = TraceRecord {
+            event,
+            stamp: self.stamp.get(),
+            cycle: self.cycle.get(),
+            addr,
+            kind,
+            id,
+            latency,
+        };
+        // a trace that stopped halfway is still worth keeping, the run is too
+        if let Err(e) = writer.record(&record) {
+            eprintln!("Failed to write the memory trace, it stops here: {}", e);
+            *trace = None;
+        }
+    }
+}
+
+// A read in flight: whom to tell when it finishes, and what it was.
+struct Completion {
+    callback: RequestCallback,
+    ctx: *mut c_void,
+    id: u64,
+    shared: Rc<Shared>,
+}
+
+// The only callback a backend sees. Unwinding out of an extern "C" function
+// would abort the process, or with Ramulator run through C++ frames, so the
+// panic stops here. Reads call back exactly once, which frees the completion,
+// so the callbacks keep running after one panicked: the caller gets every
+// ctx back and decides from the error whether to go on.
+extern "C" fn trampoline(req: *mut Request, data: *mut c_void) {
+    if data.is_null() {
+        return; // not a read, nobody waits for it
+    }
+    let completion = unsafe { Box::from_raw(data as *mut Completion) };
+    let req = unsafe { &*req };
+    let shared = &completion.shared;
+    let latency = (req.depart - req.arrive).clamp(0, u32::MAX as i64) as u32;
+    shared.log(TraceEvent::Completed, req.addr, RequestKind::Read, completion.id, latency);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| (completion.callback)(req, completion.ctx)));
+    if let Err(payload) = result {
+        // the first one is reported, later ones in the same tick follow from it
+        shared.panicked.borrow_mut().get_or_insert(payload);
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+// A read callback's panic. It keeps the original payload so the caller can
+// carry on unwinding with it, and compares and prints by its message.
+#[derive(Clone)]
+pub struct CallbackPanic {
+    message: String,
+    payload: Arc<Mutex<Option<Box<dyn Any + Send>>>>,
+}
+
+impl CallbackPanic {
+    fn new(payload: Box<dyn Any + Send>) -> Self {
+        CallbackPanic {
+            message: panic_message(&*payload),
+            payload: Arc::new(Mutex::new(Some(payload))),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    // Resume the panic with its payload. Clones share it, once one has
+    // resumed the others panic with the message.
+    pub fn resume(self) -> ! {
+        let payload = self.payload.lock().unwrap_or_else(|e| e.into_inner()).take();
+        match payload {
+            Some(payload) => panic::resume_unwind(payload),
+            None => panic!("{}", self.message),
+        }
+    }
+}
+
+impl PartialEq for CallbackPanic {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message
+    }
+}
+
+impl Eq for CallbackPanic {}
+
+impl fmt::Debug for CallbackPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CallbackPanic({:?})", self.message)
+    }
+}
+
+// What a backend call can fail with. The shim catches every C++ exception
+// and reports it as one of these rather than letting it reach Rust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryError {
+    ConfigParse(String), // the config could not be read or is invalid
+    UnknownImpl(String), // it names an implementation Ramulator does not have
+    NotInitialized,
+    AlreadyInitialized,
+    Finished, // used after finish
+    QueueFull,
+    UnsupportedRequest(RequestKind), // not a request type of this DRAM standard
+    CallbackPanicked(CallbackPanic), // a read callback panicked
+    Internal(String),         // any other exception in the shim
+}
+
+impl MemoryError {
+    // The DramStatus codes of MyWrapper.h.
+    #[cfg_attr(not(any(feature = "ramulator", feature = "ramulator-static")), allow(dead_code))]
+    fn from_status(status: c_int, msg: String) -> Self {
+        match status {
+            1 => MemoryError::ConfigParse(msg),
+            2 => MemoryError::UnknownImpl(msg),
+            3 => MemoryError::NotInitialized,
+            4 => MemoryError::QueueFull,
+            _ => MemoryError::Internal(format!("status {}: {}", status, msg)),
+        }
+    }
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryError::ConfigParse(msg) => write!(f, "invalid DRAM config: {}", msg),
+            MemoryError::UnknownImpl(msg) => write!(f, "unknown implementation: {}", msg),
+            MemoryError::NotInitialized => write!(f, "the memory system is not initialised"),
+            MemoryError::AlreadyInitialized => write!(f, "the memory system is already initialised"),
+            MemoryError::Finished => write!(f, "the memory system has finished"),
+            MemoryError::QueueFull => write!(f, "the request queue is full"),
+            MemoryError::UnsupportedRequest(kind) => {
+                write!(f, "the memory system does not support {} requests", kind)
+            }
+            MemoryError::CallbackPanicked(p) => write!(f, "a read callback panicked: {}", p.message()),
+            MemoryError::Internal(msg) => write!(f, "DRAM backend error: {}", msg),
+        }
+    }
+}
+
+impl Error for MemoryError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendKind {
+    Ramulator,       // libwrapper.so loaded at run time
+    RamulatorStatic, // the shim linked into the binary
+    Mock,            // the built-in model
+}
+
+impl BackendKind {
+    pub const ALL: [BackendKind; 3] = [
+        BackendKind::Ramulator,
+        BackendKind::RamulatorStatic,
+        BackendKind::Mock,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        BackendKind::ALL.into_iter().find(|kind| kind.name() == s)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackendKind::Ramulator => "ramulator",
+            BackendKind::RamulatorStatic => "ramulator-static",
+            BackendKind::Mock => "mock",
+        }
+    }
+
+    // The feature that builds this backend is named after it.
+    pub fn is_compiled(&self) -> bool {
+        match self {
+            BackendKind::Ramulator => cfg!(feature = "ramulator"),
+            BackendKind::RamulatorStatic => cfg!(feature = "ramulator-static"),
+            BackendKind::Mock => cfg!(feature = "mock"),
+        }
+    }
+
+    pub fn compiled() -> Vec<BackendKind> {
+        BackendKind::ALL.into_iter().filter(BackendKind::is_compiled).collect()
+    }
+
+    // The backend to use when none is asked for: a linked-in Ramulator, then
+    // a shim to load, then the built-in model.
+    pub fn preferred() -> BackendKind {
+        if cfg!(feature = "ramulator-static") {
+            BackendKind::RamulatorStatic
+        } else if cfg!(feature = "ramulator") && (library_path().is_some() || !cfg!(feature = "mock")) {
+            BackendKind::Ramulator
+        } else {
+            BackendKind::Mock
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Where a MemoryInterface is in its life: init once, then requests and
+// ticks, then finish once. Dropping finishes an initialised backend first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lifecycle {
+    Created,
+    Initialized,
+    Finished,
+}
+
+enum Backend {
+    #[cfg(any(feature = "ramulator", feature = "ramulator-static"))]
+    Ramulator(Wrapper),
+    #[cfg(feature = "mock")]
+    Mock(RefCell<MockDram>),
+}
+
+pub struct MemoryInterface<'lib> {
+    backend: Backend,
+    kind: BackendKind,
+    state: Cell<Lifecycle>,
+    shared: Rc<Shared>,
+    next_id: Cell<u64>,
+    request_types: RefCell<Vec<String>>, // in type id order, known once initialised
+    _lib: PhantomData<&'lib ()>,
+}
+
+impl MemoryInterface<'static> {
+    /// Load the library for the rest of the process, once per path, and
+    /// create a backend on it.
+    ///
+    /// # Safety
+    /// The library at `path` must be a build of MyWrapper.cpp; loading it runs
+    /// its initialisers and its symbols are called with the shim's signatures.
+    #[cfg(feature = "ramulator")]
+    pub unsafe fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        static LOADED: OnceLock<Mutex<HashMap<String, &'static libloading::Library>>> = OnceLock::new();
+        let mut loaded = LOADED
+            .get_or_init(Mutex::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let lib = match loaded.get(path) {
+            Some(lib) => *lib,
+            None => {
+                let lib: &'static libloading::Library = Box::leak(Box::new(libloading::Library::new(path)?));
+                loaded.insert(path.to_string(), lib);
+                lib
+            }
+        };
+        drop(loaded);
+        MemoryInterface::new(lib)
+    }
+
+    /// The given backend, or an error naming the feature it needs.
+    ///
+    /// # Safety
+    /// For [`BackendKind::Ramulator`], as for `open` on the shim
+    /// [`library_path`] names.
+    pub unsafe fn open_backend(kind: BackendKind) -> Result<Self, Box<dyn Error>> {
+        if !kind.is_compiled() {
+            return Err(format!(
+                "the {} backend is not compiled in, enable the `{}` feature",
+                kind, kind
+            )
+            .into());
+        }
+        match kind {
+            #[cfg(feature = "ramulator")]
+            BackendKind::Ramulator => match library_path() {
+                Some(path) => MemoryInterface::open(&path),
+                None => Err("no Ramulator shim to load, set MEMORY_INTERFACE_LIB \
+                             or build with RAMULATOR_DIR"
+                    .into()),
+            },
+            #[cfg(feature = "ramulator-static")]
+            BackendKind::RamulatorStatic => Ok(MemoryInterface::with_backend(
+                Backend::Ramulator(Wrapper::linked()?),
+                kind,
+            )),
+            #[cfg(feature = "mock")]
+            BackendKind::Mock => Ok(MemoryInterface::mock()),
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    }
+
+    /// The [`BackendKind::preferred`] backend.
+    ///
+    /// # Safety
+    /// As for [`MemoryInterface::open_backend`].
+    pub unsafe fn open_default() -> Result<Self, Box<dyn Error>> {
+        MemoryInterface::open_backend(BackendKind::preferred())
+    }
+
+    #[cfg(feature = "mock")]
+    pub fn mock() -> Self {
+        MemoryInterface::with_backend(
+            Backend::Mock(RefCell::new(MockDram::default())),
+            BackendKind::Mock,
+        )
+    }
+}
+
+impl<'lib> MemoryInterface<'lib> {
+    /// # Safety
+    /// `lib` must be a build of MyWrapper.cpp, see [`MemoryInterface::open`].
+    #[cfg(feature = "ramulator")]
+    pub unsafe fn new(lib: &'lib libloading::Library) -> Result<Self, Box<dyn Error>> {
+        let wrapper = Wrapper::load(lib)?;
+        Ok(MemoryInterface::with_backend(
+            Backend::Ramulator(wrapper),
+            BackendKind::Ramulator,
+        ))
+    }
+
+    fn with_backend(backend: Backend, kind: BackendKind) -> Self {
+        MemoryInterface {
+            backend,
+            kind,
+            state: Cell::new(Lifecycle::Created),
+            shared: Rc::default(),
+            next_id: Cell::new(0),
+            request_types: RefCell::default(),
+            _lib: PhantomData,
+        }
+    }
+
+    pub fn state(&self) -> Lifecycle {
+        self.state.get()
+    }
+
+    fn require(&self, state: Lifecycle) -> Result<(), MemoryError> {
+        match (self.state.get(), state) {
+            (current, wanted) if current == wanted => Ok(()),
+            (Lifecycle::Finished, _) => Err(MemoryError::Finished),
+            (Lifecycle::Initialized, _) => Err(MemoryError::AlreadyInitialized),
+            (Lifecycle::Created, _) => Err(MemoryError::NotInitialized),
+        }
+    }
+
+    // Run an init that leaves the backend untouched if it fails.
+    fn initialize(&self, init: impl FnOnce() -> Result<(), MemoryError>) -> Result<(), MemoryError> {
+        self.require(Lifecycle::Created)?;
+        init()?;
+        *self.request_types.borrow_mut() = match &self.backend {
+            #[cfg(any(feature = "ramulator", feature = "ramulator-static"))]
+            Backend::Ramulator(w) => w.request_types()?,
+            #[cfg(feature = "mock")]
+            Backend::Mock(_) => MockDram::REQUEST_TYPES.map(str::to_string).to_vec(),
+        };
+        self.state.set(Lifecycle::Initialized);
+        Ok(())
+    }
+
+    // The kinds the DRAM standard has, empty before init.
+    pub fn supported_requests(&self) -> Vec<RequestKind> {
+        self.request_types
+            .borrow()
+            .iter()
+            .filter_map(|name| RequestKind::parse(name))
+            .collect()
+    }
+
+    fn type_id(&self, kind: RequestKind) -> Result<c_int, MemoryError> {
+        self.request_types
+            .borrow()
+            .iter()
+            .position(|name| name == kind.name())
+            .map(|id| id as c_int)
+            .ok_or(MemoryError::UnsupportedRequest(kind))
+    }
+
+    pub fn kind(&self) -> BackendKind {
+        self.kind
+    }
+
+    pub fn backend_name(&self) -> &'static str {
+        self.kind.name()
+    }
+
+    // Log every send attempt and every completion from now on.
+    pub fn set_trace(&self, writer: TraceWriter) {
+        *self.shared.trace.borrow_mut() = Some(writer);
+    }
+
+    // Stop logging and hand the trace back, flushed.
+    pub fn take_trace(&self) -> std::io::Result<Option<TraceWriter>> {
+        let mut writer = self.shared.trace.borrow_mut().take();
+        if let Some(writer) = writer.as_mut() {
+            writer.flush()?;
+        }
+        Ok(writer)
+    }
+
+    // The caller's notion of time, stamped on the trace records that follow.
+    pub fn set_stamp(&self, stamp: u64) {
+        self.shared.stamp.set(stamp);
+    }
+
+    // Memory ticks since init.
+    pub fn cycle(&self) -> u64 {
+        self.shared.cycle.get()
+    }
+
+    #[cfg_attr(not(any(feature = "ramulator", feature = "ramulator-static")), allow(unused_variables))]
+    pub fn init(&self, config_path: &str) -> Result<(), MemoryError> {
+        self.initialize(|| match &self.backend {
+            #[cfg(any(feature = "ramulator", feature = "ramulator-static"))]
+            Backend::Ramulator(w) => w.init(config_path),
+            #[cfg(feature = "mock")]
+            Backend::Mock(_) => Ok(()),
+        })
+    }
+
+    #[cfg_attr(not(any(feature = "ramulator", feature = "ramulator-static")), allow(unused_variables))]
+    pub fn init_from_string(&self, yaml: &str) -> Result<(), MemoryError> {
+        self.initialize(|| match &self.backend {
+            #[cfg(any(feature = "ramulator", feature = "ramulator-static"))]
+            Backend::Ramulator(w) => w.init_from_string(yaml),
+            #[cfg(feature = "mock")]
+            Backend::Mock(_) => Ok(()),
+        })
+    }
+
+    // DRAM clock period in ns
+    pub fn tck(&self) -> Result<f32, MemoryError> {
+        self.require(Lifecycle::Initialized)?;
+        match &self.backend {
+            #[cfg(any(feature = "ramulator", feature = "ramulator-static"))]
+            Backend::Ramulator(w) => w.tck(),
+            #[cfg(feature = "mock")]
+            Backend::Mock(m) => Ok(m.borrow().tck),
+        }
+    }
+
+    pub fn frontend_tick(&self) -> Result<(), MemoryError> {
+        self.require(Lifecycle::Initialized)?;
+        match &self.backend {
+            #[cfg(any(feature = "ramulator", feature = "ramulator-static"))]
+            Backend::Ramulator(w) => w.frontend_tick(),
+            #[cfg(feature = "mock")]
+            Backend::Mock(_) => Ok(()),
+        }
+    }
+
+    // Reads finish, and call back, in here. A callback that panicked since
+    // the last call makes it fail, the hook has printed where by then; the
+    // other reads that finished in the tick have still called back.
+    pub fn memory_tick(&self) -> Result<(), MemoryError> {
+        self.require(Lifecycle::Initialized)?;
+        self.shared.cycle.set(self.shared.cycle.get() + 1);
+        match &self.backend {
+            #[cfg(any(feature = "ramulator", feature = "ramulator-static"))]
+            Backend::Ramulator(w) => w.memory_tick()?,
+            #[cfg(feature = "mock")]
+            Backend::Mock(m) => {
+                // callbacks may send new requests, so none run under the borrow
+                let done = m.borrow_mut().tick();
+                for (mut req, callback, data) in done {
+                    callback(&mut req, data);
+                }
+            }
+        }
+        match self.shared.panicked.borrow_mut().take() {
+            Some(payload) => Err(MemoryError::CallbackPanicked(CallbackPanic::new(payload))),
+            None => Ok(()),
+        }
+    }
+
+    /// Queue a request, [`MemoryError::QueueFull`] if the backend has no room
+    /// for it or [`MemoryError::UnsupportedRequest`] if its standard has no
+    /// such kind. Reads call `callback` with `ctx` from a later
+    /// [`MemoryInterface::memory_tick`], other kinds never call back.
+    ///
+    /// # Safety
+    /// `callback` must be able to handle `ctx` whenever memory_tick runs,
+    /// until the read has called back or the interface is dropped.
+    pub unsafe fn send_request(
+        &self,
+        addr: i64,
+        kind: RequestKind,
+        callback: RequestCallback,
+        ctx: *mut c_void,
+    ) -> Result<(), MemoryError> {
+        self.require(Lifecycle::Initialized)?;
+        let type_id = self.type_id(kind)?;
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let data = match kind {
+            RequestKind::Read => Box::into_raw(Box::new(Completion {
+                callback,
+                ctx,
+                id,
+                shared: Rc::clone(&self.shared),
+            })) as *mut c_void,
+            _ => ptr::null_mut(),
+        };
+        let sent = match &self.backend {
+            #[cfg(any(feature = "ramulator", feature = "ramulator-static"))]
+            Backend::Ramulator(w) => w.send(addr, type_id, trampoline, data),
+            #[cfg(feature = "mock")]
+            Backend::Mock(m) => match m.borrow_mut().send(addr, type_id, trampoline, data) {
+                true => Ok(()),
+                false => Err(MemoryError::QueueFull),
+            },
+        };
+        let event = match sent {
+            Ok(()) => TraceEvent::Sent,
+            Err(_) => TraceEvent::Refused,
+        };
+        self.shared.log(event, addr, kind, id, 0);
+        if sent.is_err() && !data.is_null() {
+            // refused, it will never call back
+            drop(Box::from_raw(data as *mut Completion));
+        }
+        sent
+    }
+
+    // Finalise the backend, which prints its statistics. Only once, and
+    // nothing can be sent or ticked afterwards.
+    pub fn finish(&self) -> Result<(), MemoryError> {
+        self.require(Lifecycle::Initialized)?;
+        self.state.set(Lifecycle::Finished);
+        if let Err(e) = self.take_trace() {
+            eprintln!("Failed to write the memory trace: {}", e);
+        }
+        match &self.backend {
+            #[cfg(any(feature = "ramulator", feature = "ramulator-static"))]
+            Backend::Ramulator(w) => w.finish(),
+            #[cfg(feature = "mock")]
+            Backend::Mock(_) => Ok(()),
+        }
+    }
+}
+
+impl Drop for MemoryInterface<'_> {
+    fn drop(&mut self) {
+        if self.state.get() == Lifecycle::Initialized {
+            if let Err(e) = self.finish() {
+                eprintln!("Failed to finish the memory system: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Marker(u32);
+
+    fn panicking(_req: &Request, ctx: *mut c_void) {
+        panic::panic_any(Marker(ctx as usize as u32));
+    }
+
+    fn counting(req: &Request, ctx: *mut c_void) {
+        if req.addr == 0 {
+            panic!("read of 0");
+        }
+        let count = unsafe { &*(ctx as *const Cell<u32>) };
+        count.set(count.get() + 1);
+    }
+
+    #[test]
+    fn completions_after_a_callback_panic() {
+        let mem = MemoryInterface::mock();
+        mem.init_from_string("").unwrap();
+        let count = Cell::new(0u32);
+        let ctx = &count as *const Cell<u32> as *mut c_void;
+        for addr in [0, 64, 128] {
+            unsafe { mem.send_request(addr, RequestKind::Read, counting, ctx) }.unwrap();
+        }
+        let err = (0..1000).find_map(|_| mem.memory_tick().err()).unwrap();
+        assert_eq!(err.to_string(), "a read callback panicked: read of 0");
+        // the reads behind the one that panicked still call back
+        for _ in 0..1000 {
+            mem.memory_tick().unwrap();
+        }
+        assert_eq!(count.get(), 2);
+    }
+
+    // A trace sink that counts the flushes reaching it. Only finish flushes
+    // the trace, so they count how often it ran.
+    struct Flushes(Rc<Cell<usize>>);
+
+    impl std::io::Write for Flushes {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.set(self.0.get() + 1);
+            Ok(())
+        }
+    }
+
+    fn traced_mock(flushes: &Rc<Cell<usize>>) -> MemoryInterface<'static> {
+        let mem = MemoryInterface::mock();
+        let sink = Box::new(Flushes(Rc::clone(flushes)));
+        mem.set_trace(TraceWriter::new(sink, trace::TraceFormat::Binary).unwrap());
+        mem
+    }
+
+    fn ignore(_req: &Request, _ctx: *mut c_void) {}
+
+    #[test]
+    fn finish_runs_once() {
+        let flushes = Rc::new(Cell::new(0));
+        let mem = traced_mock(&flushes);
+        assert_eq!(mem.finish(), Err(MemoryError::NotInitialized));
+        mem.init_from_string("").unwrap();
+        assert_eq!(mem.init_from_string(""), Err(MemoryError::AlreadyInitialized));
+        assert_eq!(mem.state(), Lifecycle::Initialized);
+        mem.finish().unwrap();
+        assert_eq!(mem.state(), Lifecycle::Finished);
+        assert_eq!(mem.finish(), Err(MemoryError::Finished));
+        drop(mem);
+        assert_eq!(flushes.get(), 1);
+    }
+
+    #[test]
+    fn nothing_runs_after_finish() {
+        let mem = MemoryInterface::mock();
+        mem.init_from_string("").unwrap();
+        mem.finish().unwrap();
+        let sent = unsafe { mem.send_request(0, RequestKind::Write, ignore, ptr::null_mut()) };
+        assert_eq!(sent, Err(MemoryError::Finished));
+        assert_eq!(mem.frontend_tick(), Err(MemoryError::Finished));
+        assert_eq!(mem.memory_tick(), Err(MemoryError::Finished));
+        assert_eq!(mem.tck(), Err(MemoryError::Finished));
+        assert_eq!(mem.init_from_string(""), Err(MemoryError::Finished));
+    }
+
+    #[test]
+    fn drop_finishes_an_open_interface() {
+        let flushes = Rc::new(Cell::new(0));
+        drop(traced_mock(&flushes));
+        // never initialised, there is nothing to finish
+        assert_eq!(flushes.get(), 0);
+        let mem = traced_mock(&flushes);
+        mem.init_from_string("").unwrap();
+        drop(mem);
+        assert_eq!(flushes.get(), 1);
+    }
+
+    #[test]
+    fn advertised_requests_can_be_sent() {
+        let config = concat!(env!("CARGO_MANIFEST_DIR"), "/../configs/example_config.yaml");
+        for kind in BackendKind::compiled() {
+            // only the backends this machine can run
+            let Ok(mem) = (unsafe { MemoryInterface::open_backend(kind) }) else {
+                continue;
+            };
+            mem.init(config).unwrap();
+            let supported = mem.supported_requests();
+            assert_eq!(supported[..2], [RequestKind::Read, RequestKind::Write], "{}", kind);
+            for request in supported {
+                let sent = unsafe { mem.send_request(0, request, ignore, ptr::null_mut()) };
+                assert_eq!(sent, Ok(()), "{} on {}", request, kind);
+                for _ in 0..100 {
+                    mem.frontend_tick().unwrap();
+                    mem.memory_tick().unwrap();
+                }
+            }
+            let refused = RequestKind::ALL.into_iter().find(|k| !mem.supported_requests().contains(k));
+            if let Some(request) = refused {
+                let sent = unsafe { mem.send_request(0, request, ignore, ptr::null_mut()) };
+                assert_eq!(sent, Err(MemoryError::UnsupportedRequest(request)));
+            }
+        }
+    }
+
+    #[test]
+    fn callback_panic_keeps_its_payload() {
+        let mem = MemoryInterface::mock();
+        mem.init_from_string("").unwrap();
+        unsafe { mem.send_request(64, RequestKind::Read, panicking, 7 as *mut c_void) }.unwrap();
+        let err = (0..1000).find_map(|_| mem.memory_tick().err()).unwrap();
+        let MemoryError::CallbackPanicked(p) = err else {
+            panic!("expected a callback panic, got {}", err);
+        };
+        assert_eq!(p.message(), "unknown panic payload");
+        // the panic is reported once
+        assert_eq!(mem.memory_tick(), Ok(()));
+
+        let copy = p.clone();
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| p.resume())).unwrap_err();
+        assert_eq!(payload.downcast_ref::<Marker>(), Some(&Marker(7)));
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| copy.resume())).unwrap_err();
+        assert_eq!(panic_message(&*payload), "unknown panic payload");
+    }
+}
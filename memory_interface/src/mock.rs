@@ -0,0 +1,89 @@
+use super::{RawCallback, Request};
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::ptr;
+
+// A stand-in for Ramulator when the shim is not built: every read takes a
+// fixed number of cycles and the data bus moves one burst at a time, so
+// requests complete in order. Like the GEM5 frontend, writes take a queue
+// slot but never call back. It only knows reads and writes.
+pub struct MockDram {
+    pub tck: f32,          // ns, DDR4-2400
+    pub read_latency: i64, // cycles from issue to data, tRCD + tCL + burst
+    pub burst_cycles: i64,
+    pub queue_depth: usize,
+    cycle: i64,
+    bus_free: i64,
+    queue: VecDeque<Pending>,
+}
+
+struct Pending {
+    due: i64,
+    req: Request,
+    callback: Option<(RawCallback, *mut c_void)>,
+}
+
+impl Default for MockDram {
+    fn default() -> Self {
+        MockDram {
+            tck: 0.833,
+            read_latency: 36,
+            burst_cycles: 4,
+            queue_depth: 32,
+            cycle: 0,
+            bus_free: 0,
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl MockDram {
+    pub const REQUEST_TYPES: [&'static str; 2] = ["read", "write"];
+
+    pub fn send(
+        &mut self,
+        addr: i64,
+        type_id: i32,
+        callback: RawCallback,
+        ctx: *mut c_void,
+    ) -> bool {
+        if self.queue.len() >= self.queue_depth {
+            return false;
+        }
+        let due = (self.cycle + self.read_latency).max(self.bus_free + self.burst_cycles);
+        self.bus_free = due;
+        self.queue.push_back(Pending {
+            due,
+            req: Request {
+                addr,
+                addr_vec: Vec::new(),
+                type_id,
+                source_id: 0,
+                command: -1,
+                final_command: -1,
+                is_stat_updated: false,
+                arrive: self.cycle,
+                depart: due,
+                scratchpad: [0; 4],
+                callback: None,
+                m_payload: ptr::null_mut(),
+            },
+            callback: if type_id == 0 { Some((callback, ctx)) } else { None },
+        });
+        true
+    }
+
+    // Advance a cycle and hand back the reads that finished, for the caller
+    // to call back once it no longer holds the model.
+    pub fn tick(&mut self) -> Vec<(Request, RawCallback, *mut c_void)> {
+        self.cycle += 1;
+        let mut done = Vec::new();
+        while self.queue.front().is_some_and(|p| p.due <= self.cycle) {
+            let p = self.queue.pop_front().unwrap();
+            if let Some((callback, ctx)) = p.callback {
+                done.push((p.req, callback, ctx));
+            }
+        }
+        done
+    }
+}
@@ -0,0 +1,213 @@
+use super::{MemoryError, RawCallback};
+use std::ffi::{c_char, c_float, c_int, c_void, CStr, CString};
+use std::ptr;
+
+type MyWrapper = *mut c_void;
+
+// DRAM_ABI_VERSION in MyWrapper.h
+const ABI_VERSION: c_int = 3;
+// room for the message of a failed call
+const ERROR_LEN: usize = 512;
+// and for the list of request types
+const TYPES_LEN: usize = 1024;
+
+// The entry points of MyWrapper.cpp, looked up in a loaded libwrapper.so
+// or linked into the binary, and the instance they act on.
+#[allow(non_snake_case)]
+pub struct Wrapper {
+    handle: MyWrapper,
+
+    // every call that can fail returns a DramStatus and fills the buffer
+    dram_init: unsafe extern "C" fn(MyWrapper, *const c_char, *mut c_char, usize) -> c_int,
+    dram_init_from_string: unsafe extern "C" fn(MyWrapper, *const c_char, *mut c_char, usize) -> c_int,
+    get_memory_tCK: unsafe extern "C" fn(MyWrapper, *mut c_float, *mut c_char, usize) -> c_int,
+    send_request: unsafe extern "C" fn(
+        MyWrapper,
+        i64,
+        c_int,
+        RawCallback,
+        *mut c_void,
+        *mut c_char,
+        usize,
+    ) -> c_int,
+    dram_request_types: unsafe extern "C" fn(MyWrapper, *mut c_char, usize, *mut c_char, usize) -> c_int,
+    frontend_tick: unsafe extern "C" fn(MyWrapper, *mut c_char, usize) -> c_int,
+    memory_system_tick: unsafe extern "C" fn(MyWrapper, *mut c_char, usize) -> c_int,
+    dram_delete: unsafe extern "C" fn(MyWrapper),
+    MyWrapper_finish: unsafe extern "C" fn(MyWrapper, *mut c_char, usize) -> c_int,
+}
+
+// Make a call with a fresh message buffer and turn its status into a result.
+fn check(call: impl FnOnce(*mut c_char, usize) -> c_int) -> Result<(), MemoryError> {
+    let mut buf = [0 as c_char; ERROR_LEN];
+    let status = call(buf.as_mut_ptr(), buf.len());
+    if status == 0 {
+        return Ok(());
+    }
+    let msg = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned();
+    Err(MemoryError::from_status(status, msg))
+}
+
+impl Drop for Wrapper {
+    fn drop(&mut self) {
+        unsafe {
+            (self.dram_delete)(self.handle);
+        }
+    }
+}
+
+#[cfg(feature = "ramulator")]
+impl Wrapper {
+    // The caller keeps `lib` loaded for as long as the wrapper lives.
+    pub unsafe fn load(lib: &libloading::Library) -> Result<Self, Box<dyn std::error::Error>> {
+        // older shims share the symbol names but not the signatures
+        let version = lib
+            .get::<unsafe extern "C" fn() -> c_int>(b"dram_abi_version")
+            .map(|f| f())
+            .unwrap_or(1);
+        if version != ABI_VERSION {
+            return Err(format!(
+                "the shim has ABI version {}, expected {}, rebuild libwrapper.so",
+                version, ABI_VERSION
+            )
+            .into());
+        }
+        let dram_new = *lib.get::<unsafe extern "C" fn() -> MyWrapper>(b"dram_new")?;
+        let mut wrapper = Wrapper {
+            handle: ptr::null_mut(),
+            dram_init: *lib.get(b"dram_init")?,
+            dram_init_from_string: *lib.get(b"dram_init_from_string")?,
+            get_memory_tCK: *lib.get(b"get_memory_tCK")?,
+            send_request: *lib.get(b"send_request")?,
+            dram_request_types: *lib.get(b"dram_request_types")?,
+            frontend_tick: *lib.get(b"frontend_tick")?,
+            memory_system_tick: *lib.get(b"memory_system_tick")?,
+            dram_delete: *lib.get(b"dram_delete")?,
+            MyWrapper_finish: *lib.get(b"MyWrapper_finish")?,
+        };
+        // only once every symbol resolved, so a missing one leaks nothing
+        wrapper.handle = Wrapper::handle(dram_new())?;
+        Ok(wrapper)
+    }
+}
+
+// build.rs compiles MyWrapper.cpp into a static library and links it, with
+// Ramulator, into every binary that enables ramulator-static.
+#[cfg(feature = "ramulator-static")]
+#[allow(non_snake_case)]
+mod linked {
+    use super::{MyWrapper, RawCallback};
+    use std::ffi::{c_char, c_float, c_int, c_void};
+
+    type ErrBuf = *mut c_char;
+
+    extern "C" {
+        pub fn dram_new() -> MyWrapper;
+        pub fn dram_init(obj: MyWrapper, config_path: *const c_char, err: ErrBuf, len: usize) -> c_int;
+        pub fn dram_init_from_string(
+            obj: MyWrapper,
+            config_yaml: *const c_char,
+            err: ErrBuf,
+            len: usize,
+        ) -> c_int;
+        pub fn get_memory_tCK(obj: MyWrapper, tck: *mut c_float, err: ErrBuf, len: usize) -> c_int;
+        pub fn send_request(
+            obj: MyWrapper,
+            addr: i64,
+            type_id: c_int,
+            callback: RawCallback,
+            ctx: *mut c_void,
+            err: ErrBuf,
+            len: usize,
+        ) -> c_int;
+        pub fn dram_request_types(
+            obj: MyWrapper,
+            types: *mut c_char,
+            types_len: usize,
+            err: ErrBuf,
+            len: usize,
+        ) -> c_int;
+        pub fn frontend_tick(obj: MyWrapper, err: ErrBuf, len: usize) -> c_int;
+        pub fn memory_system_tick(obj: MyWrapper, err: ErrBuf, len: usize) -> c_int;
+        pub fn dram_delete(obj: MyWrapper);
+        pub fn MyWrapper_finish(obj: MyWrapper, err: ErrBuf, len: usize) -> c_int;
+    }
+}
+
+#[cfg(feature = "ramulator-static")]
+impl Wrapper {
+    pub fn linked() -> Result<Self, MemoryError> {
+        Ok(Wrapper {
+            handle: Wrapper::handle(unsafe { linked::dram_new() })?,
+            dram_init: linked::dram_init,
+            dram_init_from_string: linked::dram_init_from_string,
+            get_memory_tCK: linked::get_memory_tCK,
+            send_request: linked::send_request,
+            dram_request_types: linked::dram_request_types,
+            frontend_tick: linked::frontend_tick,
+            memory_system_tick: linked::memory_system_tick,
+            dram_delete: linked::dram_delete,
+            MyWrapper_finish: linked::MyWrapper_finish,
+        })
+    }
+}
+
+impl Wrapper {
+    // dram_new returns null when it cannot allocate the wrapper
+    fn handle(handle: MyWrapper) -> Result<MyWrapper, MemoryError> {
+        if handle.is_null() {
+            return Err(MemoryError::Internal("dram_new failed".to_string()));
+        }
+        Ok(handle)
+    }
+
+    pub fn init(&self, config_path: &str) -> Result<(), MemoryError> {
+        let c_path = CString::new(config_path)
+            .map_err(|_| MemoryError::ConfigParse("config path contains a NUL byte".to_string()))?;
+        check(|err, len| unsafe { (self.dram_init)(self.handle, c_path.as_ptr(), err, len) })
+    }
+
+    pub fn init_from_string(&self, yaml: &str) -> Result<(), MemoryError> {
+        let c_yaml = CString::new(yaml)
+            .map_err(|_| MemoryError::ConfigParse("config contains a NUL byte".to_string()))?;
+        check(|err, len| unsafe { (self.dram_init_from_string)(self.handle, c_yaml.as_ptr(), err, len) })
+    }
+
+    pub fn tck(&self) -> Result<f32, MemoryError> {
+        let mut tck = 0.0;
+        check(|err, len| unsafe { (self.get_memory_tCK)(self.handle, &mut tck, err, len) })?;
+        Ok(tck)
+    }
+
+    pub fn frontend_tick(&self) -> Result<(), MemoryError> {
+        check(|err, len| unsafe { (self.frontend_tick)(self.handle, err, len) })
+    }
+
+    pub fn memory_tick(&self) -> Result<(), MemoryError> {
+        check(|err, len| unsafe { (self.memory_system_tick)(self.handle, err, len) })
+    }
+
+    // Names in type id order.
+    pub fn request_types(&self) -> Result<Vec<String>, MemoryError> {
+        let mut buf = [0 as c_char; TYPES_LEN];
+        check(|err, len| unsafe {
+            (self.dram_request_types)(self.handle, buf.as_mut_ptr(), buf.len(), err, len)
+        })?;
+        let list = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy();
+        Ok(list.split(',').map(str::to_string).collect())
+    }
+
+    pub unsafe fn send(
+        &self,
+        addr: i64,
+        type_id: c_int,
+        callback: RawCallback,
+        ctx: *mut c_void,
+    ) -> Result<(), MemoryError> {
+        check(|err, len| (self.send_request)(self.handle, addr, type_id, callback, ctx, err, len))
+    }
+
+    pub fn finish(&self) -> Result<(), MemoryError> {
+        check(|err, len| unsafe { (self.MyWrapper_finish)(self.handle, err, len) })
+    }
+}
@@ -0,0 +1,418 @@
+use super::RequestKind;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+// A log of every request offered to the memory and every read it finished.
+// The binary form is MAGIC, a little-endian u32 version and then fixed-size
+// records; the CSV form has a header line and one record per line. Readers
+// tell them apart by the first bytes, writers by a .csv extension.
+const MAGIC: &[u8; 8] = b"MEMTRACE";
+const VERSION: u32 = 1;
+// event, kind, stamp, cycle, addr, id, latency
+const RECORD_BYTES: usize = 1 + 1 + 8 + 8 + 8 + 8 + 4;
+const CSV_HEADER: &str = "event,stamp,cycle,addr,kind,id,latency";
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    Format { record: usize, msg: String }, // counting from 1
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceError::Io(e) => write!(f, "{}", e),
+            TraceError::Format { record, msg } => write!(f, "record {}: {}", record, msg),
+        }
+    }
+}
+
+impl Error for TraceError {}
+
+impl From<io::Error> for TraceError {
+    fn from(e: io::Error) -> Self {
+        TraceError::Io(e)
+    }
+}
+
+// The discriminants are the binary encoding, and index ALL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceEvent {
+    Sent = 0,      // the backend took the request
+    Refused = 1,   // it was full
+    Completed = 2, // a read came back
+}
+
+impl TraceEvent {
+    const ALL: [TraceEvent; 3] = [TraceEvent::Sent, TraceEvent::Refused, TraceEvent::Completed];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TraceEvent::Sent => "sent",
+            TraceEvent::Refused => "refused",
+            TraceEvent::Completed => "completed",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        TraceEvent::ALL.into_iter().find(|event| event.name() == s)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub event: TraceEvent,
+    pub stamp: u64, // the caller's time, see MemoryInterface::set_stamp
+    pub cycle: u64, // memory ticks so far
+    pub addr: i64,
+    pub kind: RequestKind,
+    pub id: u64,      // one per attempt, a completion repeats its send's
+    pub latency: u32, // DRAM cycles, completions only
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    Binary,
+    Csv,
+}
+
+impl TraceFormat {
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".csv") {
+            TraceFormat::Csv
+        } else {
+            TraceFormat::Binary
+        }
+    }
+}
+
+// A request kind's byte in the binary form, its place in RequestKind::ALL.
+fn kind_code(kind: RequestKind) -> u8 {
+    RequestKind::ALL.iter().position(|k| *k == kind).unwrap() as u8
+}
+
+pub struct TraceWriter {
+    out: BufWriter<Box<dyn Write>>,
+    format: TraceFormat,
+    pub records: usize,
+}
+
+impl TraceWriter {
+    pub fn create(path: &str) -> io::Result<Self> {
+        TraceWriter::new(Box::new(File::create(path)?), TraceFormat::from_path(path))
+    }
+
+    pub fn new(out: Box<dyn Write>, format: TraceFormat) -> io::Result<Self> {
+        let mut out = BufWriter::new(out);
+        match format {
+            TraceFormat::Binary => {
+                out.write_all(MAGIC)?;
+                out.write_all(&VERSION.to_le_bytes())?;
+            }
+            TraceFormat::Csv => writeln!(out, "{}", CSV_HEADER)?,
+        }
+        Ok(TraceWriter {
+            out,
+            format,
+            records: 0,
+        })
+    }
+
+    pub fn record(&mut self, r: &TraceRecord) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Binary => {
+                let mut buf = Vec::with_capacity(RECORD_BYTES);
+                buf.push(r.event as u8);
+                buf.push(kind_code(r.kind));
+                buf.extend_from_slice(&r.stamp.to_le_bytes());
+                buf.extend_from_slice(&r.cycle.to_le_bytes());
+                buf.extend_from_slice(&r.addr.to_le_bytes());
+                buf.extend_from_slice(&r.id.to_le_bytes());
+                buf.extend_from_slice(&r.latency.to_le_bytes());
+                self.out.write_all(&buf)?;
+            }
+            TraceFormat::Csv => writeln!(
+                self.out,
+                "{},{},{},{},{},{},{}",
+                r.event.name(),
+                r.stamp,
+                r.cycle,
+                r.addr,
+                r.kind,
+                r.id,
+                r.latency
+            )?,
+        }
+        self.records += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// Reads back either format, one record at a time.
+pub struct TraceReader {
+    input: BufReader<Box<dyn Read>>,
+    format: TraceFormat,
+    record: usize,
+}
+
+impl TraceReader {
+    pub fn open(path: &str) -> Result<Self, TraceError> {
+        TraceReader::new(Box::new(File::open(path)?))
+    }
+
+    pub fn new(input: Box<dyn Read>) -> Result<Self, TraceError> {
+        let mut input = BufReader::new(input);
+        let format = if input.fill_buf()?.starts_with(MAGIC) {
+            let mut header = [0u8; 12];
+            input.read_exact(&mut header)?;
+            let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+            if version != VERSION {
+                return Err(TraceError::Format {
+                    record: 0,
+                    msg: format!("unsupported trace version {}", version),
+                });
+            }
+            TraceFormat::Binary
+        } else {
+            let mut header = String::new();
+            input.read_line(&mut header)?;
+            if header.trim() != CSV_HEADER {
+                return Err(TraceError::Format {
+                    record: 0,
+                    msg: "neither a binary trace nor a CSV one".to_string(),
+                });
+            }
+            TraceFormat::Csv
+        };
+        Ok(TraceReader {
+            input,
+            format,
+            record: 0,
+        })
+    }
+
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    fn error(&self, msg: String) -> TraceError {
+        TraceError::Format {
+            record: self.record,
+            msg,
+        }
+    }
+
+    fn read_binary(&mut self) -> Result<Option<TraceRecord>, TraceError> {
+        let mut buf = [0u8; RECORD_BYTES];
+        let mut filled = 0;
+        while filled < RECORD_BYTES {
+            match self.input.read(&mut buf[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(self.error("truncated record".to_string())),
+                n => filled += n,
+            }
+        }
+        let u64_at = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+        let event = TraceEvent::ALL
+            .get(buf[0] as usize)
+            .copied()
+            .ok_or_else(|| self.error(format!("unknown event {}", buf[0])))?;
+        let kind = RequestKind::ALL
+            .get(buf[1] as usize)
+            .copied()
+            .ok_or_else(|| self.error(format!("unknown request kind {}", buf[1])))?;
+        Ok(Some(TraceRecord {
+            event,
+            kind,
+            stamp: u64_at(2),
+            cycle: u64_at(10),
+            addr: u64_at(18) as i64,
+            id: u64_at(26),
+            latency: u32::from_le_bytes(buf[34..38].try_into().unwrap()),
+        }))
+    }
+
+    fn read_csv(&mut self) -> Result<Option<TraceRecord>, TraceError> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let fields: Vec<&str> = line.trim().split(',').collect();
+        if fields.len() != 7 {
+            return Err(self.error(format!("expected 7 fields, got {}", fields.len())));
+        }
+        let number = |i: usize| {
+            fields[i]
+                .parse::<u64>()
+                .map_err(|_| self.error(format!("`{}` is not a number", fields[i])))
+        };
+        let latency = fields[6]
+            .parse::<u32>()
+            .map_err(|_| self.error(format!("`{}` is not a latency", fields[6])))?;
+        Ok(Some(TraceRecord {
+            event: TraceEvent::parse(fields[0])
+                .ok_or_else(|| self.error(format!("unknown event `{}`", fields[0])))?,
+            stamp: number(1)?,
+            cycle: number(2)?,
+            addr: fields[3]
+                .parse()
+                .map_err(|_| self.error(format!("`{}` is not an address", fields[3])))?,
+            kind: RequestKind::parse(fields[4])
+                .ok_or_else(|| self.error(format!("unknown request kind `{}`", fields[4])))?,
+            id: number(5)?,
+            latency,
+        }))
+    }
+}
+
+impl Iterator for TraceReader {
+    type Item = Result<TraceRecord, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.record += 1;
+        let record = match self.format {
+            TraceFormat::Binary => self.read_binary(),
+            TraceFormat::Csv => self.read_csv(),
+        };
+        record.transpose()
+    }
+}
+
+// The whole trace at once.
+pub fn read_trace(path: &str) -> Result<Vec<TraceRecord>, TraceError> {
+    TraceReader::open(path)?.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    // A Write whose bytes stay readable after the writer is dropped.
+    #[derive(Clone, Default)]
+    struct Sink(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn records() -> Vec<TraceRecord> {
+        let mut records = Vec::new();
+        for (i, kind) in RequestKind::ALL.into_iter().enumerate() {
+            for event in TraceEvent::ALL {
+                records.push(TraceRecord {
+                    event,
+                    stamp: 100 * i as u64,
+                    cycle: u64::MAX - i as u64,
+                    addr: -64 * i as i64,
+                    kind,
+                    id: i as u64,
+                    latency: u32::MAX - i as u32,
+                });
+            }
+        }
+        records
+    }
+
+    fn encode(format: TraceFormat, records: &[TraceRecord]) -> Vec<u8> {
+        let sink = Sink::default();
+        let mut writer = TraceWriter::new(Box::new(sink.clone()), format).unwrap();
+        for r in records {
+            writer.record(r).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(writer.records, records.len());
+        drop(writer);
+        let bytes = sink.0.lock().unwrap().clone();
+        bytes
+    }
+
+    fn decode(bytes: Vec<u8>) -> Result<Vec<TraceRecord>, TraceError> {
+        TraceReader::new(Box::new(Cursor::new(bytes)))?.collect()
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let bytes = encode(TraceFormat::Binary, &records());
+        assert_eq!(bytes.len(), 12 + RECORD_BYTES * records().len());
+        assert_eq!(decode(bytes).unwrap(), records());
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let bytes = encode(TraceFormat::Csv, &records());
+        assert!(bytes.starts_with(CSV_HEADER.as_bytes()));
+        assert_eq!(decode(bytes).unwrap(), records());
+    }
+
+    #[test]
+    fn event_codes_follow_all() {
+        for (i, event) in TraceEvent::ALL.into_iter().enumerate() {
+            assert_eq!(event as usize, i);
+        }
+    }
+
+    #[test]
+    fn truncated_binary_record() {
+        let mut bytes = encode(TraceFormat::Binary, &records()[..2]);
+        bytes.pop();
+        let mut reader = TraceReader::new(Box::new(Cursor::new(bytes))).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next() {
+            Some(Err(TraceError::Format { record, .. })) => assert_eq!(record, 2),
+            _ => panic!("expected a truncated record"),
+        }
+    }
+
+    #[test]
+    fn bad_headers() {
+        assert!(matches!(
+            decode(b"event,stamp\n".to_vec()),
+            Err(TraceError::Format { record: 0, .. })
+        ));
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(decode(bytes), Err(TraceError::Format { record: 0, .. })));
+    }
+
+    #[test]
+    fn bad_csv_fields() {
+        for line in [
+            "sent,1,2,3,read,4",
+            "sent,1,2,3,read,4,4294967296",
+            "lost,1,2,3,read,4,0",
+            "sent,1,2,3,fetch,4,0",
+            "sent,x,2,3,read,4,0",
+        ] {
+            let text = format!("{}\n{}\n", CSV_HEADER, line);
+            assert!(
+                matches!(decode(text.into_bytes()), Err(TraceError::Format { record: 1, .. })),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn bad_binary_codes() {
+        let mut bytes = encode(TraceFormat::Binary, &records()[..1]);
+        bytes[12] = TraceEvent::ALL.len() as u8;
+        assert!(decode(bytes).is_err());
+        let mut bytes = encode(TraceFormat::Binary, &records()[..1]);
+        bytes[13] = RequestKind::ALL.len() as u8;
+        assert!(decode(bytes).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+use super::config::{AddrMapping, DramConfig};
+use super::rng::with_rng;
+use rand::Rng;
+use std::fmt;
+
+const LEVELS: [&str; 6] = ["channel", "rank", "bankgroup", "bank", "row", "column"];
+
+// Ramulator's DDR4 organisation presets, as channel, rank, bankgroup, bank,
+// row and column counts. Channel and rank come from the config instead.
+pub const DDR4_PRESETS: &[(&str, [usize; 6])] = &[
+    ("DDR4_2Gb_x4", [1, 1, 4, 4, 1 << 15, 1 << 10]),
+    ("DDR4_2Gb_x8", [1, 1, 4, 4, 1 << 14, 1 << 10]),
+    ("DDR4_2Gb_x16", [1, 1, 2, 4, 1 << 14, 1 << 10]),
+    ("DDR4_4Gb_x4", [1, 1, 4, 4, 1 << 16, 1 << 10]),
+    ("DDR4_4Gb_x8", [1, 1, 4, 4, 1 << 15, 1 << 10]),
+    ("DDR4_4Gb_x16", [1, 1, 2, 4, 1 << 15, 1 << 10]),
+    ("DDR4_8Gb_x4", [1, 1, 4, 4, 1 << 17, 1 << 10]),
+    ("DDR4_8Gb_x8", [1, 1, 4, 4, 1 << 16, 1 << 10]),
+    ("DDR4_8Gb_x16", [1, 1, 2, 4, 1 << 16, 1 << 10]),
+    ("DDR4_16Gb_x4", [1, 1, 4, 4, 1 << 17, 1 << 11]),
+    ("DDR4_16Gb_x8", [1, 1, 4, 4, 1 << 17, 1 << 10]),
+    ("DDR4_16Gb_x16", [1, 1, 2, 4, 1 << 17, 1 << 10]),
+];
+
+// DDR4 bursts 8 beats on a 64-bit channel, so one request moves 64 bytes.
+const PREFETCH: usize = 8;
+const CHANNEL_WIDTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AddrVec {
+    pub channel: usize,
+    pub rank: usize,
+    pub bankgroup: usize,
+    pub bank: usize,
+    pub row: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for AddrVec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ch{} ra{} bg{} ba{} ro{} co{}",
+            self.channel, self.rank, self.bankgroup, self.bank, self.row, self.column
+        )
+    }
+}
+
+// The RoBaRaCoCh linear mapper: from the least significant bit up, after
+// the transaction offset, channel, column, rank, bankgroup, bank and row.
+#[derive(Clone, Debug)]
+pub struct AddrMapper {
+    pub preset: String,
+    pub count: [usize; 6],
+    bits: [u32; 6],
+    tx_offset: u32,
+}
+
+fn log2(n: usize, what: &str) -> Result<u32, String> {
+    if n == 0 || !n.is_power_of_two() {
+        return Err(format!("{} count {} is not a power of two", what, n));
+    }
+    Ok(n.trailing_zeros())
+}
+
+impl AddrMapper {
+    pub fn new(preset: &str, channels: usize, ranks: usize) -> Result<Self, String> {
+        let (_, preset_count) = DDR4_PRESETS
+            .iter()
+            .find(|(name, _)| *name == preset)
+            .ok_or_else(|| format!("unknown DDR4 organisation preset {}", preset))?;
+        let mut count = *preset_count;
+        count[0] = channels;
+        count[1] = ranks;
+        let mut bits = [0; 6];
+        for level in 0..LEVELS.len() {
+            bits[level] = log2(count[level], LEVELS[level])?;
+        }
+        // a request covers a whole burst of columns
+        bits[5] -= log2(PREFETCH, "prefetch")?;
+        Ok(AddrMapper {
+            preset: preset.to_string(),
+            count,
+            bits,
+            tx_offset: log2(PREFETCH * CHANNEL_WIDTH / 8, "transaction size")?,
+        })
+    }
+
+    pub fn from_config(config: &DramConfig) -> Result<Self, String> {
+        if config.mapping != AddrMapping::RoBaRaCoCh {
+            return Err(format!("address mapper {} is not RoBaRaCoCh", config.mapping.name()));
+        }
+        let org = &config.dram.org;
+        AddrMapper::new(&org.preset, org.channel, org.rank)
+    }
+
+    pub fn map(&self, addr: i64) -> AddrVec {
+        let mut a = (addr as u64) >> self.tx_offset;
+        let mut take = |level: usize| {
+            let v = a & ((1u64 << self.bits[level]) - 1);
+            a >>= self.bits[level];
+            v as usize
+        };
+        let channel = take(0);
+        let column = take(5);
+        let rank = take(1);
+        let bankgroup = take(2);
+        let bank = take(3);
+        let row = take(4);
+        AddrVec {
+            channel,
+            rank,
+            bankgroup,
+            bank,
+            row,
+            column,
+        }
+    }
+
+    // The inverse of map, for the first byte of the burst.
+    pub fn compose(&self, v: &AddrVec) -> i64 {
+        let mut a = v.row as u64;
+        for (level, value) in [(3, v.bank), (2, v.bankgroup), (1, v.rank), (5, v.column), (0, v.channel)] {
+            a = (a << self.bits[level]) | value as u64;
+        }
+        (a << self.tx_offset) as i64
+    }
+
+    pub fn num_banks(&self) -> usize {
+        self.count[0] * self.count[1] * self.count[2] * self.count[3]
+    }
+
+    pub fn bank_id(&self, v: &AddrVec) -> usize {
+        ((v.channel * self.count[1] + v.rank) * self.count[2] + v.bankgroup) * self.count[3] + v.bank
+    }
+
+    // Row and column are left at zero.
+    pub fn bank_vec(&self, id: usize) -> AddrVec {
+        AddrVec {
+            channel: id / (self.count[3] * self.count[2] * self.count[1]),
+            rank: id / (self.count[3] * self.count[2]) % self.count[1],
+            bankgroup: id / self.count[3] % self.count[2],
+            bank: id % self.count[3],
+            row: 0,
+            column: 0,
+        }
+    }
+
+    pub fn bank_name(&self, id: usize) -> String {
+        let v = self.bank_vec(id);
+        format!("ch{} ra{} bg{} ba{}", v.channel, v.rank, v.bankgroup, v.bank)
+    }
+
+    fn bank_addr(&self, id: usize, row: usize, column: usize) -> i64 {
+        self.compose(&AddrVec {
+            row: row % self.count[4],
+            column: column % (1 << self.bits[5]),
+            ..self.bank_vec(id)
+        })
+    }
+}
+
+// Synthetic read traffic on rows drawn from the seeded RNG. Row 0 of every
+// bank holds the low addresses the design itself uses and is never drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrafficPattern {
+    Conflict, // one bank, a new row every access
+    Spread,   // every bank in turn on the same row
+}
+
+impl TrafficPattern {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "conflict" => Some(TrafficPattern::Conflict),
+            "spread" => Some(TrafficPattern::Spread),
+            _ => None,
+        }
+    }
+
+    pub fn generate(&self, mapper: &AddrMapper, count: usize) -> Vec<i64> {
+        let rows = mapper.count[4];
+        with_rng(|rng| match self {
+            TrafficPattern::Conflict => {
+                let mut row = rng.gen_range(1..rows);
+                (0..count)
+                    .map(|_| {
+                        let addr = mapper.bank_addr(0, row, 0);
+                        // on to any other row but 0
+                        row = 1 + (row - 1 + rng.gen_range(1..rows - 1)) % (rows - 1);
+                        addr
+                    })
+                    .collect()
+            }
+            TrafficPattern::Spread => {
+                let banks = mapper.num_banks();
+                let row = rng.gen_range(1..rows);
+                (0..count).map(|i| mapper.bank_addr(i % banks, row, i / banks)).collect()
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_bit_order() {
+        let mapper = AddrMapper::new("DDR4_8Gb_x8", 2, 2).unwrap();
+        // 64-byte bursts, then 1 channel, 7 column, 1 rank, 2 bankgroup,
+        // 2 bank and 16 row bits
+        let at = |level: &str, bit: u32| {
+            let v = mapper.map(1 << bit);
+            let expect = match level {
+                "channel" => AddrVec { channel: 1, ..Default::default() },
+                "column" => AddrVec { column: 1, ..Default::default() },
+                "rank" => AddrVec { rank: 1, ..Default::default() },
+                "bankgroup" => AddrVec { bankgroup: 1, ..Default::default() },
+                "bank" => AddrVec { bank: 1, ..Default::default() },
+                _ => AddrVec { row: 1, ..Default::default() },
+            };
+            assert_eq!(v, expect, "{} at bit {}", level, bit);
+        };
+        at("channel", 6);
+        at("column", 7);
+        at("rank", 14);
+        at("bankgroup", 15);
+        at("bank", 17);
+        at("row", 19);
+        assert_eq!(mapper.map(63), AddrVec::default());
+        assert_eq!(mapper.num_banks(), 64);
+    }
+
+    #[test]
+    fn compose_inverts_map() {
+        let mapper = AddrMapper::new("DDR4_4Gb_x16", 1, 2).unwrap();
+        for addr in [0i64, 64, 0x1234_5640, 0x7fff_ffc0] {
+            assert_eq!(mapper.compose(&mapper.map(addr)), addr);
+        }
+        for id in 0..mapper.num_banks() {
+            let v = mapper.bank_vec(id);
+            assert_eq!(mapper.bank_id(&v), id);
+            assert_eq!(mapper.bank_id(&mapper.map(mapper.compose(&v))), id);
+        }
+        assert_eq!(mapper.bank_name(5), "ch0 ra0 bg1 ba1");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            AddrMapper::new("DDR5_8Gb_x8", 1, 1).unwrap_err(),
+            "unknown DDR4 organisation preset DDR5_8Gb_x8"
+        );
+        assert_eq!(
+            AddrMapper::new("DDR4_8Gb_x8", 3, 1).unwrap_err(),
+            "channel count 3 is not a power of two"
+        );
+        assert_eq!(AddrMapper::new("DDR4_8Gb_x8", 1, 0).unwrap_err(), "rank count 0 is not a power of two");
+        let config = DramConfig {
+            mapping: AddrMapping::ChRaBaRoCo,
+            ..Default::default()
+        };
+        assert!(AddrMapper::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn traffic() {
+        let mapper = AddrMapper::new("DDR4_8Gb_x8", 1, 1).unwrap();
+        let conflict = TrafficPattern::Conflict.generate(&mapper, 1000);
+        let rows: Vec<_> = conflict.iter().map(|a| mapper.map(*a)).collect();
+        assert!(rows.iter().all(|v| mapper.bank_id(v) == 0 && v.row != 0));
+        assert!(rows.windows(2).all(|pair| pair[0].row != pair[1].row));
+
+        let spread = TrafficPattern::Spread.generate(&mapper, 17);
+        let banks: Vec<_> = spread.iter().map(|a| mapper.bank_id(&mapper.map(*a))).collect();
+        assert_eq!(banks[..16], (0..16).collect::<Vec<_>>()[..]);
+        let row = mapper.map(spread[0]).row;
+        assert_ne!(row, 0);
+        assert_eq!(mapper.map(spread[16]), AddrVec { row, column: 1, ..Default::default() });
+        assert_eq!(TrafficPattern::parse("stride"), None);
+    }
+}
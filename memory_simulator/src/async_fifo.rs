@@ -0,0 +1,135 @@
+use super::clock::{Clock, Phase};
+use std::collections::VecDeque;
+
+const SYNC_STAGES: usize = 2;
+
+// A FIFO between two clock domains. Each side sees the other's pointer
+// updates only after they pass a synchronizer of SYNC_STAGES flops clocked
+// by the receiving domain, so a push becomes poppable, and a pop frees a slot
+// for the writer, a few receiver cycles late.
+pub struct AsyncFIFO<T: Sized> {
+    pub payload: VecDeque<(usize, T)>, // data with the stamp the reader sees it
+    pub freed: VecDeque<usize>,        // stamps at which the writer sees popped slots
+    capacity: usize,
+    write_clock: Clock,
+    read_clock: Clock,
+}
+
+impl<T: Sized> AsyncFIFO<T> {
+    pub fn new(capacity: usize, write_clock: Clock, read_clock: Clock) -> Self {
+        AsyncFIFO {
+            payload: VecDeque::new(),
+            freed: VecDeque::new(),
+            capacity,
+            write_clock,
+            read_clock,
+        }
+    }
+
+    fn synchronized(clock: &Clock, stamp: usize) -> usize {
+        clock.next_edge(stamp, Phase::Evaluate) + clock.cycles(SYNC_STAGES - 1)
+    }
+
+    // full as seen from the write domain at the stamp
+    pub fn is_full(&self, stamp: usize) -> bool {
+        let pending_free = self.freed.iter().filter(|s| **s > stamp).count();
+        self.payload.len() + pending_free >= self.capacity
+    }
+
+    // empty as seen from the read domain at the stamp
+    pub fn is_empty(&self, stamp: usize) -> bool {
+        self.payload.front().is_none_or(|(visible, _)| *visible > stamp)
+    }
+
+    // the stamp at which the reader sees the data, None if full
+    pub fn push(&mut self, stamp: usize, data: T) -> Option<usize> {
+        if self.is_full(stamp) {
+            return None;
+        }
+        self.freed.retain(|s| *s > stamp);
+        let visible = Self::synchronized(&self.read_clock, stamp);
+        self.payload.push_back((visible, data));
+        Some(visible)
+    }
+
+    pub fn front(&self, stamp: usize) -> Option<&T> {
+        if self.is_empty(stamp) {
+            None
+        } else {
+            self.payload.front().map(|(_, data)| data)
+        }
+    }
+
+    pub fn pop(&mut self, stamp: usize) -> Option<T> {
+        if self.is_empty(stamp) {
+            return None;
+        }
+        let freed = Self::synchronized(&self.write_clock, stamp);
+        self.freed.push_back(freed);
+        self.payload.pop_front().map(|(_, data)| data)
+    }
+
+    // entries pushed and not yet popped, visible to the reader or not
+    pub fn occupancy(&self) -> usize {
+        self.payload.len()
+    }
+
+    // earliest stamp at which the reader will see data it cannot see yet
+    pub fn next_visible(&self) -> Option<usize> {
+        self.payload.front().map(|(visible, _)| *visible)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn async_fifo() {
+        // a fast writer and a reader at a third of its rate
+        let mut fifo = AsyncFIFO::new(2, Clock::new(100), Clock::new(300));
+        assert_eq!(fifo.push(50, 1), Some(600));
+        assert_eq!(fifo.push(150, 2), Some(600));
+        assert_eq!(fifo.push(250, 3), None);
+        assert_eq!(fifo.next_visible(), Some(600));
+        assert!(fifo.is_empty(599));
+        assert_eq!(fifo.pop(599), None);
+        assert_eq!(fifo.front(600), Some(&1));
+        assert_eq!(fifo.pop(600), Some(1));
+        // the writer sees the freed slot two of its own edges later
+        assert!(fifo.is_full(799));
+        assert!(!fifo.is_full(800));
+        assert_eq!(fifo.push(850, 3), Some(1200));
+        assert_eq!(fifo.occupancy(), 2);
+        assert_eq!(fifo.pop(900), Some(2));
+        assert!(fifo.is_empty(900));
+        assert_eq!(fifo.next_visible(), Some(1200));
+    }
+
+    #[test]
+    fn async_fifo_fast_reader() {
+        // the reader runs at three times the writer's rate
+        let mut fifo = AsyncFIFO::new(1, Clock::new(300), Clock::new(100));
+        assert_eq!(fifo.push(450, 1), Some(600));
+        assert_eq!(fifo.push(750, 2), None);
+        assert_eq!(fifo.pop(599), None);
+        assert_eq!(fifo.pop(600), Some(1));
+        // the pop crosses back on the slow side, two writer edges later
+        assert!(fifo.is_full(1199));
+        assert!(!fifo.is_full(1200));
+        assert_eq!(fifo.push(1350, 2), Some(1500));
+        assert_eq!(fifo.front(1500), Some(&2));
+    }
+
+    #[test]
+    fn async_fifo_same_period() {
+        let mut fifo = AsyncFIFO::new(4, Clock::new(100), Clock::new(100));
+        assert_eq!(fifo.push(50, 1), Some(200));
+        assert_eq!(fifo.push(150, 2), Some(300));
+        assert!(fifo.is_empty(199));
+        assert_eq!(fifo.pop(200), Some(1));
+        assert_eq!(fifo.pop(250), None);
+        assert_eq!(fifo.pop(300), Some(2));
+        assert_eq!(fifo.occupancy(), 0);
+    }
+}
@@ -0,0 +1,499 @@
+use super::checkpoint::{CheckpointError, Tokens};
+use super::rng::with_rng;
+use rand::Rng;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Replacement {
+    Lru,
+    Random,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WritePolicy {
+    WriteBack,    // write-allocate, dirty lines go to memory on eviction
+    WriteThrough, // no-allocate, every write goes to memory
+}
+
+// Sizes are in units of the request address, a word in the generated designs.
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+    pub size: usize,
+    pub ways: usize,
+    pub line: usize,
+    pub replacement: Replacement,
+    pub write: WritePolicy,
+    pub mshrs: usize,
+    pub hit_latency: usize, // core cycles
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            size: 256,
+            ways: 4,
+            line: 8,
+            replacement: Replacement::Lru,
+            write: WritePolicy::WriteBack,
+            mshrs: 4,
+            hit_latency: 1,
+        }
+    }
+}
+
+impl CacheConfig {
+    // Parse `key=value` pairs separated by commas, any of
+    // size=N ways=N line=N repl=lru|random write=back|through mshrs=N hit=N,
+    // with the rest left at their defaults.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut config = CacheConfig::default();
+        for field in s.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got `{}`", field))?;
+            let num = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid {} `{}`", key, value))
+            };
+            match key {
+                "size" => config.size = num()?,
+                "ways" => config.ways = num()?,
+                "line" => config.line = num()?,
+                "mshrs" => config.mshrs = num()?,
+                "hit" => config.hit_latency = num()?,
+                "repl" => {
+                    config.replacement = match value {
+                        "lru" => Replacement::Lru,
+                        "random" => Replacement::Random,
+                        _ => return Err(format!("unknown replacement `{}`", value)),
+                    }
+                }
+                "write" => {
+                    config.write = match value {
+                        "back" => WritePolicy::WriteBack,
+                        "through" => WritePolicy::WriteThrough,
+                        _ => return Err(format!("unknown write policy `{}`", value)),
+                    }
+                }
+                _ => return Err(format!("unknown cache parameter `{}`", key)),
+            }
+        }
+        if config.ways == 0 || config.line == 0 || config.mshrs == 0 || config.hit_latency == 0 {
+            return Err("ways, line, mshrs and hit must be non-zero".to_string());
+        }
+        if config.size == 0 || config.size % (config.ways * config.line) != 0 {
+            return Err(format!(
+                "size {} is not a multiple of ways * line = {}",
+                config.size,
+                config.ways * config.line
+            ));
+        }
+        Ok(config)
+    }
+
+    pub fn sets(&self) -> usize {
+        self.size / (self.ways * self.line)
+    }
+}
+
+impl fmt::Display for CacheConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "size={},ways={},line={},repl={},write={},mshrs={},hit={}",
+            self.size,
+            self.ways,
+            self.line,
+            match self.replacement {
+                Replacement::Lru => "lru",
+                Replacement::Random => "random",
+            },
+            match self.write {
+                WritePolicy::WriteBack => "back",
+                WritePolicy::WriteThrough => "through",
+            },
+            self.mshrs,
+            self.hit_latency
+        )
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct CacheStats {
+    pub read_hits: usize,
+    pub read_misses: usize,
+    pub write_hits: usize,
+    pub write_misses: usize,
+    pub mshr_merges: usize, // misses to a line already being fetched
+    pub mshr_full: usize,   // accesses refused for want of an MSHR
+    pub fills: usize,
+    pub writebacks: usize,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let hits = self.read_hits + self.write_hits;
+        let total = hits + self.read_misses + self.write_misses;
+        if total == 0 {
+            0.0
+        } else {
+            hits as f64 / total as f64
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Hit,
+    Miss,    // the line is, or will be, fetched by an MSHR
+    Blocked, // no MSHR free, retry later
+}
+
+#[derive(Clone, Copy, Default)]
+struct Line {
+    valid: bool,
+    dirty: bool,
+    tag: usize,
+    last_used: usize,
+}
+
+struct Mshr {
+    line: i64,
+    readers: Vec<(i64, usize)>, // address and reorder buffer tag
+    dirty: bool, // a write missed on the line while it was fetched
+    sent: bool,
+}
+
+// A timing model only: tags and state are tracked here, the data stays in
+// the design's arrays.
+pub struct Cache {
+    pub config: CacheConfig,
+    pub stats: CacheStats,
+    sets: Vec<Vec<Line>>,
+    mshrs: Vec<Mshr>,
+    writebacks: VecDeque<i64>,
+    accesses: usize,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        Cache {
+            sets: vec![vec![Line::default(); config.ways]; config.sets()],
+            config,
+            stats: CacheStats::default(),
+            mshrs: Vec::new(),
+            writebacks: VecDeque::new(),
+            accesses: 0,
+        }
+    }
+
+    pub fn line_addr(&self, addr: i64) -> i64 {
+        addr - addr.rem_euclid(self.config.line as i64)
+    }
+
+    fn locate(&self, addr: i64) -> (usize, usize) {
+        let line = addr.div_euclid(self.config.line as i64) as usize;
+        (line % self.sets.len(), line / self.sets.len())
+    }
+
+    // Find the line holding addr and mark it most recently used.
+    fn lookup(&mut self, addr: i64) -> Option<&mut Line> {
+        let (set, tag) = self.locate(addr);
+        self.accesses += 1;
+        let now = self.accesses;
+        let line = self.sets[set]
+            .iter_mut()
+            .find(|l| l.valid && l.tag == tag)?;
+        line.last_used = now;
+        Some(line)
+    }
+
+    fn mshr(&mut self, addr: i64) -> Result<&mut Mshr, ()> {
+        let line = self.line_addr(addr);
+        if let Some(pos) = self.mshrs.iter().position(|m| m.line == line) {
+            self.stats.mshr_merges += 1;
+            return Ok(&mut self.mshrs[pos]);
+        }
+        if self.mshrs.len() >= self.config.mshrs {
+            self.stats.mshr_full += 1;
+            return Err(());
+        }
+        self.mshrs.push(Mshr {
+            line,
+            readers: Vec::new(),
+            dirty: false,
+            sent: false,
+        });
+        Ok(self.mshrs.last_mut().unwrap())
+    }
+
+    // A miss keeps the tag to hand back from fill, a hit leaves it to the
+    // caller.
+    pub fn read(&mut self, addr: i64, tag: usize) -> Access {
+        if self.lookup(addr).is_some() {
+            self.stats.read_hits += 1;
+            return Access::Hit;
+        }
+        match self.mshr(addr) {
+            Ok(mshr) => {
+                mshr.readers.push((addr, tag));
+                self.stats.read_misses += 1;
+                Access::Miss
+            }
+            Err(()) => Access::Blocked,
+        }
+    }
+
+    // With write-through the caller also sends the write to memory.
+    pub fn write(&mut self, addr: i64) -> Access {
+        let write_back = self.config.write == WritePolicy::WriteBack;
+        if let Some(line) = self.lookup(addr) {
+            line.dirty |= write_back;
+            self.stats.write_hits += 1;
+            return Access::Hit;
+        }
+        if !write_back {
+            self.stats.write_misses += 1;
+            return Access::Miss;
+        }
+        match self.mshr(addr) {
+            Ok(mshr) => {
+                mshr.dirty = true;
+                self.stats.write_misses += 1;
+                Access::Miss
+            }
+            Err(()) => Access::Blocked,
+        }
+    }
+
+    // Line fetches not yet accepted by memory.
+    pub fn unsent(&self) -> Vec<i64> {
+        self.mshrs
+            .iter()
+            .filter(|m| !m.sent)
+            .map(|m| m.line)
+            .collect()
+    }
+
+    pub fn mark_sent(&mut self, line: i64) {
+        if let Some(mshr) = self.mshrs.iter_mut().find(|m| m.line == line) {
+            mshr.sent = true;
+        }
+    }
+
+    pub fn next_writeback(&self) -> Option<i64> {
+        self.writebacks.front().copied()
+    }
+
+    pub fn writeback_sent(&mut self) {
+        self.writebacks.pop_front();
+        self.stats.writebacks += 1;
+    }
+
+    pub fn is_pending(&self, line: i64) -> bool {
+        self.mshrs.iter().any(|m| m.line == line && m.sent)
+    }
+
+    // Whether memory still has work to do on the cache's behalf.
+    pub fn busy(&self) -> bool {
+        !self.mshrs.is_empty() || !self.writebacks.is_empty()
+    }
+
+    // Install a fetched line, returning the addresses and tags of the reads
+    // waiting on it. A dirty victim is queued for write-back.
+    pub fn fill(&mut self, line: i64) -> Vec<(i64, usize)> {
+        let Some(pos) = self.mshrs.iter().position(|m| m.line == line) else {
+            return Vec::new();
+        };
+        let mshr = self.mshrs.remove(pos);
+        let (set, tag) = self.locate(line);
+        let way = match self.sets[set].iter().position(|l| !l.valid) {
+            Some(way) => way,
+            None => match self.config.replacement {
+                Replacement::Lru => (0..self.config.ways)
+                    .min_by_key(|w| self.sets[set][*w].last_used)
+                    .unwrap(),
+                Replacement::Random => with_rng(|rng| rng.gen_range(0..self.config.ways)),
+            },
+        };
+        let victim = self.sets[set][way];
+        if victim.valid && victim.dirty {
+            let victim_line = ((victim.tag * self.sets.len() + set) * self.config.line) as i64;
+            self.writebacks.push_back(victim_line);
+        }
+        self.accesses += 1;
+        self.sets[set][way] = Line {
+            valid: true,
+            dirty: mshr.dirty,
+            tag,
+            last_used: self.accesses,
+        };
+        self.stats.fills += 1;
+        mshr.readers
+    }
+
+    // Tags, MSHRs and queued write-backs, on one checkpoint line.
+    pub fn save(&self, out: &mut String) {
+        let s = &self.stats;
+        write!(
+            out,
+            "cache {} {} {} {} {} {} {} {} {} {}",
+            self.config,
+            self.accesses,
+            s.read_hits,
+            s.read_misses,
+            s.write_hits,
+            s.write_misses,
+            s.mshr_merges,
+            s.mshr_full,
+            s.fills,
+            s.writebacks
+        )
+        .unwrap();
+        for line in self.sets.iter().flatten() {
+            write!(out, " {} {} {} {}", line.valid, line.dirty, line.tag, line.last_used).unwrap();
+        }
+        write!(out, " {}", self.mshrs.len()).unwrap();
+        for mshr in self.mshrs.iter() {
+            write!(out, " {} {} {} {}", mshr.line, mshr.dirty, mshr.sent, mshr.readers.len()).unwrap();
+            for (addr, tag) in mshr.readers.iter() {
+                write!(out, " {} {}", addr, tag).unwrap();
+            }
+        }
+        write!(out, " {}", self.writebacks.len()).unwrap();
+        for line in self.writebacks.iter() {
+            write!(out, " {}", line).unwrap();
+        }
+        out.push('\n');
+    }
+
+    pub fn restore(&mut self, t: &mut Tokens) -> Result<(), CheckpointError> {
+        t.expect("cache")?;
+        let config = t.word("cache config")?;
+        if config != self.config.to_string() {
+            return Err(CheckpointError::Format(format!(
+                "cache is {}, checkpoint has {}",
+                self.config, config
+            )));
+        }
+        self.accesses = t.num("cache accesses")?;
+        self.stats = CacheStats {
+            read_hits: t.num("read hits")?,
+            read_misses: t.num("read misses")?,
+            write_hits: t.num("write hits")?,
+            write_misses: t.num("write misses")?,
+            mshr_merges: t.num("MSHR merges")?,
+            mshr_full: t.num("MSHR full")?,
+            fills: t.num("fills")?,
+            writebacks: t.num("write-backs")?,
+        };
+        for line in self.sets.iter_mut().flatten() {
+            *line = Line {
+                valid: t.num("line valid")?,
+                dirty: t.num("line dirty")?,
+                tag: t.num("line tag")?,
+                last_used: t.num("line last use")?,
+            };
+        }
+        self.mshrs.clear();
+        for _ in 0..t.num::<usize>("MSHR count")? {
+            let mut mshr = Mshr {
+                line: t.num("MSHR line")?,
+                dirty: t.num("MSHR dirty")?,
+                sent: t.num("MSHR sent")?,
+                readers: Vec::new(),
+            };
+            for _ in 0..t.num::<usize>("MSHR reader count")? {
+                mshr.readers.push((t.num("reader address")?, t.num("reader tag")?));
+            }
+            self.mshrs.push(mshr);
+        }
+        self.writebacks.clear();
+        for _ in 0..t.num::<usize>("write-back count")? {
+            self.writebacks.push_back(t.num("write-back line")?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(spec: &str) -> Cache {
+        Cache::new(CacheConfig::parse(spec).unwrap())
+    }
+
+    #[test]
+    fn parse() {
+        let config = CacheConfig::parse("size=64, ways=2,line=4,repl=random,write=through,mshrs=1,hit=3").unwrap();
+        assert_eq!(config.sets(), 8);
+        assert_eq!(config.to_string(), "size=64,ways=2,line=4,repl=random,write=through,mshrs=1,hit=3");
+        assert_eq!(CacheConfig::parse("").unwrap().to_string(), CacheConfig::default().to_string());
+
+        let err = |s: &str| CacheConfig::parse(s).unwrap_err();
+        assert_eq!(err("size"), "expected key=value, got `size`");
+        assert_eq!(err("size=big"), "invalid size `big`");
+        assert_eq!(err("repl=fifo"), "unknown replacement `fifo`");
+        assert_eq!(err("write=around"), "unknown write policy `around`");
+        assert_eq!(err("banks=2"), "unknown cache parameter `banks`");
+        assert_eq!(err("hit=0"), "ways, line, mshrs and hit must be non-zero");
+        assert_eq!(err("size=100"), "size 100 is not a multiple of ways * line = 32");
+    }
+
+    #[test]
+    fn misses_merge_and_fill_with_their_tags() {
+        let mut cache = cache("size=32,ways=2,line=8,mshrs=1");
+        assert_eq!(cache.read(3, 0), Access::Miss);
+        assert_eq!(cache.read(5, 1), Access::Miss); // same line
+        assert_eq!(cache.read(8, 2), Access::Blocked); // out of MSHRs
+        assert_eq!(cache.unsent(), vec![0]);
+        assert!(!cache.is_pending(0));
+        cache.mark_sent(0);
+        assert!(cache.is_pending(0) && cache.busy());
+        assert_eq!(cache.fill(0), vec![(3, 0), (5, 1)]);
+        assert!(!cache.busy());
+        assert_eq!(cache.read(7, 3), Access::Hit);
+        // a line nobody waits on
+        assert_eq!(cache.fill(8), vec![]);
+        assert_eq!((cache.stats.read_hits, cache.stats.read_misses, cache.stats.mshr_merges), (1, 2, 1));
+        assert_eq!(cache.stats.mshr_full, 1);
+    }
+
+    #[test]
+    fn lru_evicts_and_writes_back_dirty_lines() {
+        // two sets of two ways, lines 0, 16 and 32 share set 0
+        let mut cache = cache("size=32,ways=2,line=8,mshrs=4");
+        for (tag, line) in [0, 16].into_iter().enumerate() {
+            cache.read(line, tag);
+            cache.mark_sent(line);
+            cache.fill(line);
+        }
+        assert_eq!(cache.write(1), Access::Hit);
+        cache.read(17, 2); // line 16 most recently used
+        cache.read(32, 3);
+        cache.fill(32);
+        assert_eq!(cache.next_writeback(), Some(0));
+        cache.writeback_sent();
+        assert_eq!(cache.next_writeback(), None);
+        assert_eq!(cache.stats.writebacks, 1);
+        assert_eq!(cache.read(17, 4), Access::Hit);
+        assert_eq!(cache.read(0, 5), Access::Miss);
+    }
+
+    #[test]
+    fn write_policies() {
+        let mut back = cache("write=back,mshrs=1");
+        assert_eq!(back.write(0), Access::Miss); // allocates, fetched dirty
+        assert_eq!(back.unsent(), vec![0]);
+        assert_eq!(back.write(64), Access::Blocked);
+
+        let mut through = cache("write=through");
+        assert_eq!(through.write(0), Access::Miss);
+        assert!(through.unsent().is_empty() && !through.busy());
+        assert_eq!(through.stats.write_misses, 1);
+    }
+}
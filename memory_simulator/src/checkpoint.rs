@@ -0,0 +1,511 @@
+use super::modules::{self, Reader, Sent};
+use super::rng::{restore_rng, rng_state};
+use super::runtime::*;
+use super::simulator::Simulator;
+use super::stats::ModuleStats;
+use super::watch::{WatchAction, WatchSpec};
+use memory_interface::{MemoryError, RequestKind};
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::rc::Rc;
+use std::str::{FromStr, SplitWhitespace};
+
+// Checkpoints are plain text, one state element per line. The memory backend
+// cannot be snapshotted, so a checkpoint carries every request memory
+// accepted and the restore replays them into a fresh backend on the memory
+// cycles they first went in on. That rebuilds its queues, open rows and
+// refresh timers, and the reads still in flight complete when they would
+// have, so a restored run carries on exactly as the saved one did.
+const VERSION: u32 = 5;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(std::io::Error),
+    Format(String),
+    Memory(MemoryError), // the backend failed while the requests were replayed
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "{}", e),
+            CheckpointError::Format(msg) => write!(f, "malformed checkpoint: {}", msg),
+            CheckpointError::Memory(e) => write!(f, "replaying memory requests: {}", e),
+        }
+    }
+}
+
+impl Error for CheckpointError {}
+
+impl From<std::io::Error> for CheckpointError {
+    fn from(e: std::io::Error) -> Self {
+        CheckpointError::Io(e)
+    }
+}
+
+impl From<MemoryError> for CheckpointError {
+    fn from(e: MemoryError) -> Self {
+        CheckpointError::Memory(e)
+    }
+}
+
+fn format_err(msg: String) -> CheckpointError {
+    CheckpointError::Format(msg)
+}
+
+pub struct Tokens<'a> {
+    iter: SplitWhitespace<'a>,
+    // pushers are &'static str, so names are resolved against the registry
+    pushers: Vec<&'static str>,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn word(&mut self, what: &str) -> Result<&'a str, CheckpointError> {
+        self.iter
+            .next()
+            .ok_or_else(|| format_err(format!("unexpected end of file, expected {}", what)))
+    }
+
+    pub fn num<N: FromStr>(&mut self, what: &str) -> Result<N, CheckpointError> {
+        let word = self.word(what)?;
+        word.parse()
+            .map_err(|_| format_err(format!("expected {}, found `{}`", what, word)))
+    }
+
+    pub fn expect(&mut self, keyword: &str) -> Result<(), CheckpointError> {
+        let word = self.word(keyword)?;
+        if word != keyword {
+            return Err(format_err(format!("expected `{}`, found `{}`", keyword, word)));
+        }
+        Ok(())
+    }
+
+    pub fn pusher(&mut self) -> Result<&'static str, CheckpointError> {
+        let word = self.word("pusher")?;
+        self.pushers
+            .iter()
+            .find(|p| **p == word)
+            .copied()
+            .ok_or_else(|| format_err(format!("unknown pusher `{}`", word)))
+    }
+
+    pub fn value<T>(&mut self, what: &str) -> Result<T, CheckpointError>
+    where
+        u64: ValueCastTo<T>,
+    {
+        Ok(ValueCastTo::<T>::cast(&self.num::<u64>(what)?))
+    }
+}
+
+pub fn save_array<T>(out: &mut String, name: &str, array: &Array<T>)
+where
+    T: Sized + Default + Clone + ValueCastTo<u64>,
+{
+    write!(out, "array {} {}", name, array.payload.len()).unwrap();
+    for value in array.payload.iter() {
+        write!(out, " {}", ValueCastTo::<u64>::cast(value)).unwrap();
+    }
+    write!(out, "\narray_write {} {}", name, array.write.q.len()).unwrap();
+    for event in array.write.q.values() {
+        write!(
+            out,
+            " {} {} {} {}",
+            event.cycle(),
+            event.addr(),
+            ValueCastTo::<u64>::cast(event.data()),
+            event.pusher()
+        )
+        .unwrap();
+    }
+    out.push('\n');
+}
+
+pub fn restore_array<T>(t: &mut Tokens, name: &str, array: &mut Array<T>) -> Result<(), CheckpointError>
+where
+    T: Sized + Default + Clone,
+    u64: ValueCastTo<T>,
+{
+    t.expect("array")?;
+    t.expect(name)?;
+    let len: usize = t.num("array length")?;
+    if len != array.payload.len() {
+        return Err(format_err(format!(
+            "{} holds {} words, checkpoint has {}",
+            name,
+            array.payload.len(),
+            len
+        )));
+    }
+    for i in 0..len {
+        array.payload[i] = t.value("array word")?;
+    }
+    t.expect("array_write")?;
+    t.expect(name)?;
+    array.write = XEQ::new();
+    for _ in 0..t.num::<usize>("write count")? {
+        let cycle = t.num("write cycle")?;
+        let addr: usize = t.num("write address")?;
+        if addr >= len {
+            return Err(format_err(format!("{} write to {} is out of range", name, addr)));
+        }
+        let data = t.value("write data")?;
+        let pusher = t.pusher()?;
+        array.write.push(ArrayWrite::new(cycle, addr, data, pusher));
+    }
+    Ok(())
+}
+
+pub fn save_fifo<T>(out: &mut String, name: &str, fifo: &FIFO<T>)
+where
+    T: Sized + ValueCastTo<u64>,
+{
+    write!(out, "fifo {} {}", name, fifo.payload.len()).unwrap();
+    for value in fifo.payload.iter() {
+        write!(out, " {}", ValueCastTo::<u64>::cast(value)).unwrap();
+    }
+    write!(out, "\nfifo_push {} {}", name, fifo.push.q.len()).unwrap();
+    for event in fifo.push.q.values() {
+        write!(
+            out,
+            " {} {} {}",
+            event.cycle(),
+            ValueCastTo::<u64>::cast(event.data()),
+            event.pusher()
+        )
+        .unwrap();
+    }
+    write!(out, "\nfifo_pop {} {}", name, fifo.pop.q.len()).unwrap();
+    for event in fifo.pop.q.values() {
+        write!(out, " {} {}", event.cycle(), event.pusher()).unwrap();
+    }
+    out.push('\n');
+}
+
+pub fn restore_fifo<T>(t: &mut Tokens, name: &str, fifo: &mut FIFO<T>) -> Result<(), CheckpointError>
+where
+    T: Sized,
+    u64: ValueCastTo<T>,
+{
+    t.expect("fifo")?;
+    t.expect(name)?;
+    fifo.payload.clear();
+    fifo.push = XEQ::new();
+    fifo.pop = XEQ::new();
+    for _ in 0..t.num::<usize>("fifo length")? {
+        fifo.payload.push_back(t.value("fifo entry")?);
+    }
+    t.expect("fifo_push")?;
+    t.expect(name)?;
+    for _ in 0..t.num::<usize>("push count")? {
+        let cycle = t.num("push cycle")?;
+        let data = t.value("push data")?;
+        let pusher = t.pusher()?;
+        fifo.push.push(FIFOPush::new(cycle, data, pusher));
+    }
+    t.expect("fifo_pop")?;
+    t.expect(name)?;
+    for _ in 0..t.num::<usize>("pop count")? {
+        let cycle = t.num("pop cycle")?;
+        let pusher = t.pusher()?;
+        fifo.pop.push(FIFOPop::new(cycle, pusher));
+    }
+    Ok(())
+}
+
+fn save_reader(out: &mut String, reader: Option<Reader>) {
+    match reader {
+        None => out.push_str(" -"),
+        Some(Reader::Design(tag)) => write!(out, " design {}", tag).unwrap(),
+        Some(Reader::Line) => out.push_str(" line"),
+        Some(Reader::Traffic) => out.push_str(" traffic"),
+    }
+}
+
+fn restore_reader(t: &mut Tokens) -> Result<Option<Reader>, CheckpointError> {
+    match t.word("reader")? {
+        "-" => Ok(None),
+        "design" => Ok(Some(Reader::Design(t.num("reader tag")?))),
+        "line" => Ok(Some(Reader::Line)),
+        "traffic" => Ok(Some(Reader::Traffic)),
+        word => Err(format_err(format!("unknown reader `{}`", word))),
+    }
+}
+
+impl Simulator {
+    pub fn save_checkpoint(&self, path: &str) -> Result<(), CheckpointError> {
+        let mut out = String::new();
+        writeln!(out, "checkpoint {}", VERSION).unwrap();
+        writeln!(out, "stamp {}", self.stamp).unwrap();
+        writeln!(out, "clock {} {}", self.clock.period, self.mem_clock.period).unwrap();
+        writeln!(
+            out,
+            "progress {} {} {} {}",
+            self.mem_ticked, self.idle_count, self.evaluated, self.mem_refused
+        )
+        .unwrap();
+        writeln!(out, "log {} {}", self.log.hash(), self.log.len()).unwrap();
+        let (seed, word_pos) = rng_state();
+        out.push_str("rng ");
+        for byte in seed {
+            write!(out, "{:02x}", byte).unwrap();
+        }
+        writeln!(out, " {}", word_pos).unwrap();
+
+        writeln!(out, "modules {}", self.modules.len()).unwrap();
+        for slot in self.modules.iter() {
+            let s = &slot.stats;
+            write!(
+                out,
+                "module {} {} {} {} {} {} {}",
+                slot.module.name(),
+                slot.triggered,
+                s.triggered,
+                s.fifo_empty,
+                s.mem_blocked,
+                s.events_queued,
+                slot.event.len()
+            )
+            .unwrap();
+            for stamp in slot.event.iter() {
+                write!(out, " {}", stamp).unwrap();
+            }
+            out.push('\n');
+        }
+        self.design.borrow().save(&mut out);
+        let watches = self.watch_labels();
+        write!(out, "watches {}", watches.len()).unwrap();
+        for label in watches.iter() {
+            let words: Vec<&str> = label.split_whitespace().collect();
+            write!(out, " {} {}", words.len(), words.join(" ")).unwrap();
+        }
+        out.push('\n');
+
+        write!(
+            out,
+            "rob {} {} {} {} {} {}",
+            self.rob.depth,
+            self.rob.in_order,
+            self.rob.head,
+            self.rob.next_tag,
+            self.rob.outstanding,
+            self.rob.done.len()
+        )
+        .unwrap();
+        for (tag, data) in self.rob.done.iter() {
+            write!(out, " {} {}", tag, ValueCastTo::<u64>::cast(data)).unwrap();
+        }
+        out.push('\n');
+        match &self.cache {
+            Some(cache) => cache.save(&mut out),
+            None => out.push_str("nocache\n"),
+        }
+        write!(out, "traffic {} {}", self.traffic_outstanding, self.traffic.len()).unwrap();
+        for addr in self.traffic.iter() {
+            write!(out, " {}", addr).unwrap();
+        }
+        write!(out, "\nbanks {}", self.banks.len()).unwrap();
+        for bank in self.banks.iter() {
+            write!(
+                out,
+                " {} {} {} {} {} {}",
+                bank.reads,
+                bank.writes,
+                bank.read_latency,
+                bank.max_latency,
+                bank.row_switches,
+                bank.last_row.map_or(-1, |row| row as i64)
+            )
+            .unwrap();
+        }
+
+        write!(out, "\nmemory {} {}", self.mem_interface.cycle(), self.mem_sent.len()).unwrap();
+        for sent in self.mem_sent.iter() {
+            write!(out, " {} {} {}", sent.cycle, sent.addr, sent.kind.name()).unwrap();
+            save_reader(&mut out, sent.reader);
+        }
+        out.push('\n');
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    // Must be called on a freshly elaborated simulator, before anything is
+    // sent to its memory.
+    pub fn restore_checkpoint(&mut self, path: &str) -> Result<(), CheckpointError> {
+        let text = fs::read_to_string(path)?;
+        let mut t = Tokens {
+            iter: text.split_whitespace(),
+            pushers: self.modules.iter().map(|m| m.module.name()).collect(),
+        };
+        t.expect("checkpoint")?;
+        let version: u32 = t.num("version")?;
+        if version != VERSION {
+            return Err(format_err(format!("unsupported version {}", version)));
+        }
+        t.expect("stamp")?;
+        self.stamp = t.num("stamp")?;
+        t.expect("clock")?;
+        let period: usize = t.num("clock period")?;
+        let mem_period: usize = t.num("memory clock period")?;
+        if period != self.clock.period || mem_period != self.mem_clock.period {
+            return Err(format_err(format!(
+                "clock periods are {} and {} for memory, checkpoint was taken with {} and {}",
+                self.clock.period, self.mem_clock.period, period, mem_period
+            )));
+        }
+        t.expect("progress")?;
+        self.mem_ticked = t.num("last memory tick")?;
+        self.idle_count = t.num("idle count")?;
+        self.evaluated = t.num("evaluated count")?;
+        self.mem_refused = t.num("memory refused")?;
+        t.expect("log")?;
+        let hash = t.num("log hash")?;
+        let count = t.num("log length")?;
+        self.log.resume(hash, count);
+        t.expect("rng")?;
+        let key = t.word("rng key")?;
+        let mut seed = [0u8; 32];
+        if key.len() != 64 || !key.is_ascii() {
+            return Err(format_err(format!("invalid rng key `{}`", key)));
+        }
+        for (i, byte) in seed.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&key[2 * i..2 * i + 2], 16)
+                .map_err(|_| format_err(format!("invalid rng key `{}`", key)))?;
+        }
+        restore_rng(seed, t.num("rng position")?);
+
+        t.expect("modules")?;
+        let count: usize = t.num("module count")?;
+        if count != self.modules.len() {
+            return Err(format_err(format!(
+                "design has {} modules, checkpoint has {}",
+                self.modules.len(),
+                count
+            )));
+        }
+        for _ in 0..count {
+            t.expect("module")?;
+            let name = t.pusher()?;
+            let id = self.module_id(name);
+            self.modules[id].triggered = t.num("module triggered")?;
+            self.modules[id].stats = ModuleStats {
+                triggered: t.num("trigger count")?,
+                fifo_empty: t.num("fifo empty count")?,
+                mem_blocked: t.num("memory blocked count")?,
+                events_queued: t.num("queued count")?,
+            };
+            self.modules[id].event.clear();
+            for _ in 0..t.num::<usize>("event count")? {
+                let stamp = t.num("event stamp")?;
+                self.modules[id].event.push_back(stamp);
+            }
+        }
+
+        self.design.borrow_mut().restore(&mut t)?;
+        t.expect("watches")?;
+        for _ in 0..t.num::<usize>("watch count")? {
+            let words = (0..t.num::<usize>("watch length")?)
+                .map(|_| t.word("watch"))
+                .collect::<Result<Vec<&str>, _>>()?;
+            let label = words.join(" ");
+            WatchSpec::parse(&label, WatchAction::Log)
+                .and_then(|spec| self.add_watch(spec))
+                .map_err(|e| format_err(format!("watch `{}`: {}", label, e)))?;
+        }
+
+        t.expect("rob")?;
+        let depth: usize = t.num("reorder buffer depth")?;
+        let in_order: bool = t.num("reorder buffer mode")?;
+        if depth != self.rob.depth || in_order != self.rob.in_order {
+            return Err(format_err(format!(
+                "reorder buffer is depth {} in_order {}, checkpoint has depth {} in_order {}",
+                self.rob.depth, self.rob.in_order, depth, in_order
+            )));
+        }
+        self.rob.head = t.num("reorder buffer head")?;
+        self.rob.next_tag = t.num("reorder buffer tail")?;
+        self.rob.outstanding = t.num("reorder buffer outstanding")?;
+        self.rob.done.clear();
+        for _ in 0..t.num::<usize>("completed count")? {
+            let tag = t.num("completed tag")?;
+            let data = t.value("completed data")?;
+            self.rob.done.insert(tag, data);
+        }
+        match &mut self.cache {
+            Some(cache) => cache.restore(&mut t)?,
+            None => t.expect("nocache")?,
+        }
+        t.expect("traffic")?;
+        self.traffic_outstanding = t.num("traffic outstanding")?;
+        self.traffic.clear();
+        for _ in 0..t.num::<usize>("traffic count")? {
+            self.traffic.push_back(t.num("traffic address")?);
+        }
+        t.expect("banks")?;
+        let count: usize = t.num("bank count")?;
+        if count != self.banks.len() {
+            return Err(format_err(format!(
+                "memory has {} banks, checkpoint has {}",
+                self.banks.len(),
+                count
+            )));
+        }
+        for bank in self.banks.iter_mut() {
+            bank.reads = t.num("bank reads")?;
+            bank.writes = t.num("bank writes")?;
+            bank.read_latency = t.num("bank latency")?;
+            bank.max_latency = t.num("bank max latency")?;
+            bank.row_switches = t.num("bank row switches")?;
+            bank.last_row = usize::try_from(t.num::<i64>("bank row")?).ok();
+        }
+
+        t.expect("memory")?;
+        let cycle: u64 = t.num("memory cycle")?;
+        let mut sent = Vec::new();
+        for _ in 0..t.num::<usize>("request count")? {
+            let at: u64 = t.num("request cycle")?;
+            let addr = t.num("request address")?;
+            let word = t.word("request kind")?;
+            let kind = RequestKind::parse(word)
+                .ok_or_else(|| format_err(format!("unknown request kind `{}`", word)))?;
+            sent.push(Sent {
+                cycle: at,
+                addr,
+                kind,
+                reader: restore_reader(&mut t)?,
+            });
+        }
+        self.replay_memory(sent, cycle)
+    }
+
+    // Bring the fresh backend to where the saved one was: the same requests
+    // on the same memory cycles. The reads it finishes on the way had been
+    // delivered before the checkpoint, the rest call back as they would have.
+    fn replay_memory(&mut self, sent: Vec<Sent>, cycle: u64) -> Result<(), CheckpointError> {
+        let mem = Rc::clone(&self.mem_interface);
+        if mem.cycle() != 0 || !self.mem_sent.is_empty() {
+            return Err(format_err("memory is not fresh".to_string()));
+        }
+        let mem_reads = self.mem_reads;
+        for req in sent {
+            if req.cycle < mem.cycle() || req.cycle > cycle {
+                return Err(format_err(format!("request at memory cycle {} is out of order", req.cycle)));
+            }
+            while mem.cycle() < req.cycle {
+                mem.frontend_tick()?;
+                mem.memory_tick()?;
+            }
+            if !modules::send(self, req.addr, req.kind, req.reader)? {
+                return Err(format_err(format!("memory refused the replayed request for {}", req.addr)));
+            }
+        }
+        while mem.cycle() < cycle {
+            mem.frontend_tick()?;
+            mem.memory_tick()?;
+        }
+        let finished = self.mem_done.borrow_mut().drain(..).count();
+        self.mem_reads = mem_reads + self.mem_reads - finished;
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use std::cell::Cell;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Evaluate, // modules run at the start of a cycle
+    Commit,   // register writes and FIFO push/pop land half a period later
+}
+
+// Two-phase clock over the global stamp, which counts time units.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Clock {
+    pub period: usize,
+}
+
+impl Clock {
+    pub fn new(period: usize) -> Self {
+        if period < 2 || !period.is_multiple_of(2) {
+            panic!("Clock period {} must be even and at least 2!", period);
+        }
+        Clock { period }
+    }
+
+    pub fn cycle(&self, stamp: usize) -> usize {
+        stamp / self.period
+    }
+
+    pub fn phase(&self, stamp: usize) -> Phase {
+        if stamp % self.period < self.period / 2 {
+            Phase::Evaluate
+        } else {
+            Phase::Commit
+        }
+    }
+
+    pub fn at(&self, cycle: usize, phase: Phase) -> usize {
+        match phase {
+            Phase::Evaluate => cycle * self.period,
+            Phase::Commit => cycle * self.period + self.period / 2,
+        }
+    }
+
+    // commit point of the cycle the stamp falls in
+    pub fn next_commit(&self, stamp: usize) -> usize {
+        self.at(self.cycle(stamp), Phase::Commit)
+    }
+
+    pub fn next_cycle(&self, stamp: usize) -> usize {
+        self.at(self.cycle(stamp) + 1, Phase::Evaluate)
+    }
+
+    pub fn cycles(&self, n: usize) -> usize {
+        n * self.period
+    }
+
+    pub fn is_edge(&self, stamp: usize, phase: Phase) -> bool {
+        stamp.is_multiple_of(self.period / 2) && self.phase(stamp) == phase
+    }
+
+    // first edge of the given phase strictly after the stamp
+    pub fn next_edge(&self, stamp: usize, phase: Phase) -> usize {
+        let edge = self.at(self.cycle(stamp), phase);
+        if edge > stamp {
+            edge
+        } else {
+            self.at(self.cycle(stamp) + 1, phase)
+        }
+    }
+
+    // first edge of either phase at or after the stamp
+    pub fn edge_at_or_after(&self, stamp: usize) -> usize {
+        let half = self.period / 2;
+        stamp.div_ceil(half) * half
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::new(100)
+    }
+}
+
+thread_local! {
+    static DISPLAY_PERIOD: Cell<usize> = const { Cell::new(100) };
+}
+
+// Stamps are printed as cycles of the simulator's clock, set here.
+pub fn set_display_clock(clock: Clock) {
+    DISPLAY_PERIOD.with(|p| p.set(clock.period));
+}
+
+pub fn cyclize(stamp: usize) -> String {
+    let period = DISPLAY_PERIOD.with(|p| p.get());
+    format!(
+        "Cycle @{}.{:02}",
+        stamp / period,
+        stamp % period * 100 / period
+    )
+}
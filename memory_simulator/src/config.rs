@@ -0,0 +1,399 @@
+use super::addr_map::DDR4_PRESETS;
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+
+const DDR4_TIMINGS: &[&str] = &[
+    "DDR4_1600J", "DDR4_1600K", "DDR4_1600L", "DDR4_1866L", "DDR4_1866M", "DDR4_1866N",
+    "DDR4_2133N", "DDR4_2133P", "DDR4_2133R", "DDR4_2400P", "DDR4_2400R", "DDR4_2400U",
+    "DDR4_2400T", "DDR4_2666T", "DDR4_2666U", "DDR4_2666V", "DDR4_2666W", "DDR4_2933V",
+    "DDR4_2933W", "DDR4_2933Y", "DDR4_2933AA", "DDR4_3200W", "DDR4_3200AA", "DDR4_3200AC",
+];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Syntax { line: usize, msg: String },
+    UnknownKey(String),
+    Invalid { key: String, value: String, expected: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Syntax { line, msg } => write!(f, "line {}: {}", line, msg),
+            ConfigError::UnknownKey(key) => write!(f, "unknown key {}", key),
+            ConfigError::Invalid { key, value, expected } => {
+                write!(f, "invalid {} `{}`, expected {}", key, value, expected)
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+fn invalid(key: &str, value: &str, expected: &str) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.to_string(),
+        value: value.to_string(),
+        expected: expected.to_string(),
+    }
+}
+
+// The names below are what Ramulator reads, keep them apart from the variants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Standard {
+    DDR4,
+}
+
+impl Standard {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Standard::DDR4 => "DDR4",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheduler {
+    Frfcfs,
+    Bliss,
+}
+
+impl Scheduler {
+    pub const ALL: [Scheduler; 2] = [Scheduler::Frfcfs, Scheduler::Bliss];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scheduler::Frfcfs => "FRFCFS",
+            Scheduler::Bliss => "BLISS",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefreshManager {
+    AllBank,
+}
+
+impl RefreshManager {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RefreshManager::AllBank => "AllBank",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowPolicy {
+    Closed { cap: usize }, // close a row after cap hits
+    Open,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddrMapping {
+    RoBaRaCoCh,
+    ChRaBaRoCo,
+    MOP4CLXOR,
+}
+
+impl AddrMapping {
+    pub const ALL: [AddrMapping; 3] = [
+        AddrMapping::RoBaRaCoCh,
+        AddrMapping::ChRaBaRoCo,
+        AddrMapping::MOP4CLXOR,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AddrMapping::RoBaRaCoCh => "RoBaRaCoCh",
+            AddrMapping::ChRaBaRoCo => "ChRaBaRoCo",
+            AddrMapping::MOP4CLXOR => "MOP4CLXOR",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Org {
+    pub preset: String,
+    pub channel: usize,
+    pub rank: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dram {
+    pub standard: Standard,
+    pub org: Org,
+    pub timing: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Controller {
+    pub scheduler: Scheduler,
+    pub refresh: RefreshManager,
+    pub row_policy: RowPolicy,
+}
+
+// Everything the wrapper hands to Ramulator. The frontend is always GEM5,
+// the one that accepts requests sent from outside, and the memory system
+// and controller are the generic ones. Both clock ratios stay at 1: the
+// driver ticks the frontend and the memory once each per memory cycle, so
+// Ramulator never sees them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DramConfig {
+    pub dram: Dram,
+    pub controller: Controller,
+    pub mapping: AddrMapping,
+}
+
+// The example config the simulator has always run with.
+impl Default for DramConfig {
+    fn default() -> Self {
+        DramConfig {
+            dram: Dram {
+                standard: Standard::DDR4,
+                org: Org {
+                    preset: "DDR4_8Gb_x8".to_string(),
+                    channel: 1,
+                    rank: 2,
+                },
+                timing: "DDR4_2400R".to_string(),
+            },
+            controller: Controller {
+                scheduler: Scheduler::Frfcfs,
+                refresh: RefreshManager::AllBank,
+                row_policy: RowPolicy::Closed { cap: 4 },
+            },
+            mapping: AddrMapping::RoBaRaCoCh,
+        }
+    }
+}
+
+impl DramConfig {
+    // Read a Ramulator YAML file over the defaults. Only the keys of the
+    // model are understood; anything else is an error rather than dropped.
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let mut config = DramConfig::default();
+        config.merge_yaml(&fs::read_to_string(path)?)?;
+        Ok(config)
+    }
+
+    pub fn merge_yaml(&mut self, text: &str) -> Result<(), ConfigError> {
+        let mut sections: Vec<(usize, &str)> = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim_end();
+            if line.trim().is_empty() {
+                continue;
+            }
+            let indent = line.len() - line.trim_start().len();
+            let Some((key, value)) = line.trim().split_once(':') else {
+                return Err(ConfigError::Syntax {
+                    line: n + 1,
+                    msg: format!("expected `key: value`, got `{}`", line.trim()),
+                });
+            };
+            while sections.last().is_some_and(|(i, _)| *i >= indent) {
+                sections.pop();
+            }
+            let value = value.trim().trim_matches('"');
+            if value.is_empty() {
+                sections.push((indent, key.trim()));
+                continue;
+            }
+            let mut path: Vec<&str> = sections.iter().map(|(_, s)| *s).collect();
+            path.push(key.trim());
+            self.set(&path.join("."), value)?;
+        }
+        self.validate()
+    }
+
+    // Apply `KEY=VALUE` overrides, KEY being the dotted YAML path as for
+    // Ramulator's own -p option, e.g. MemorySystem.DRAM.org.rank=1.
+    pub fn merge(&mut self, overrides: &[String]) -> Result<(), ConfigError> {
+        for o in overrides {
+            let (key, value) = o
+                .split_once('=')
+                .ok_or_else(|| invalid("override", o, "KEY=VALUE"))?;
+            self.set(key.trim(), value.trim())?;
+        }
+        self.validate()
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let num = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| invalid(key, value, "a number"))
+        };
+        let fixed = |expected: &str| {
+            if value == expected {
+                Ok(())
+            } else {
+                Err(invalid(key, value, expected))
+            }
+        };
+        match key {
+            "Frontend.impl" => fixed("GEM5")?,
+            "Frontend.clock_ratio" | "MemorySystem.clock_ratio" => fixed("1")?,
+            "MemorySystem.impl" => fixed("GenericDRAM")?,
+            "MemorySystem.DRAM.impl" => {
+                fixed(Standard::DDR4.name())?;
+                self.dram.standard = Standard::DDR4;
+            }
+            "MemorySystem.DRAM.org.preset" => self.dram.org.preset = value.to_string(),
+            "MemorySystem.DRAM.org.channel" => self.dram.org.channel = num()?,
+            "MemorySystem.DRAM.org.rank" => self.dram.org.rank = num()?,
+            "MemorySystem.DRAM.timing.preset" => self.dram.timing = value.to_string(),
+            "MemorySystem.Controller.impl" => fixed("Generic")?,
+            "MemorySystem.Controller.Scheduler.impl" => {
+                self.controller.scheduler = Scheduler::ALL
+                    .into_iter()
+                    .find(|s| s.name() == value)
+                    .ok_or_else(|| invalid(key, value, "FRFCFS or BLISS"))?
+
+            }
+            "MemorySystem.Controller.RefreshManager.impl" => {
+                fixed(RefreshManager::AllBank.name())?;
+                self.controller.refresh = RefreshManager::AllBank;
+            }
+            "MemorySystem.Controller.RowPolicy.impl" => {
+                self.controller.row_policy = match (value, self.controller.row_policy) {
+                    ("ClosedRowPolicy", RowPolicy::Closed { cap }) => RowPolicy::Closed { cap },
+                    ("ClosedRowPolicy", RowPolicy::Open) => RowPolicy::Closed { cap: 4 },
+                    ("OpenRowPolicy", _) => RowPolicy::Open,
+                    _ => return Err(invalid(key, value, "ClosedRowPolicy or OpenRowPolicy")),
+                }
+            }
+            // only the closed policy has a cap, it does not pick the policy
+            "MemorySystem.Controller.RowPolicy.cap" => match self.controller.row_policy {
+                RowPolicy::Closed { .. } => self.controller.row_policy = RowPolicy::Closed { cap: num()? },
+                RowPolicy::Open => return Err(invalid(key, value, "ClosedRowPolicy, the open policy has no cap")),
+            },
+            "MemorySystem.AddrMapper.impl" => {
+                self.mapping = AddrMapping::ALL
+                    .into_iter()
+                    .find(|m| m.name() == value)
+                    .ok_or_else(|| invalid(key, value, "RoBaRaCoCh, ChRaBaRoCo or MOP4CLXOR"))?
+            }
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let org = &self.dram.org;
+        if !DDR4_PRESETS.iter().any(|(name, _)| *name == org.preset) {
+            return Err(invalid("org preset", &org.preset, "a DDR4 organisation preset"));
+        }
+        if !DDR4_TIMINGS.contains(&self.dram.timing.as_str()) {
+            return Err(invalid("timing preset", &self.dram.timing, "a DDR4 timing preset"));
+        }
+        for (key, n) in [("channel", org.channel), ("rank", org.rank)] {
+            if !n.is_power_of_two() {
+                return Err(invalid(key, &n.to_string(), "a power of two"));
+            }
+        }
+        if self.controller.row_policy == (RowPolicy::Closed { cap: 0 }) {
+            return Err(invalid("row policy cap", "0", "a positive number"));
+        }
+        Ok(())
+    }
+
+    pub fn to_yaml(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "Frontend:\n  impl: GEM5\n  clock_ratio: 1\n").unwrap();
+        writeln!(out, "MemorySystem:\n  impl: GenericDRAM\n  clock_ratio: 1\n").unwrap();
+        writeln!(out, "  DRAM:\n    impl: {}", self.dram.standard.name()).unwrap();
+        writeln!(out, "    org:\n      preset: {}", self.dram.org.preset).unwrap();
+        writeln!(out, "      channel: {}\n      rank: {}", self.dram.org.channel, self.dram.org.rank).unwrap();
+        writeln!(out, "    timing:\n      preset: {}\n", self.dram.timing).unwrap();
+        writeln!(out, "  Controller:\n    impl: Generic").unwrap();
+        writeln!(out, "    Scheduler:\n      impl: {}", self.controller.scheduler.name()).unwrap();
+        writeln!(out, "    RefreshManager:\n      impl: {}", self.controller.refresh.name()).unwrap();
+        match self.controller.row_policy {
+            RowPolicy::Closed { cap } => {
+                writeln!(out, "    RowPolicy:\n      impl: ClosedRowPolicy\n      cap: {}", cap).unwrap()
+            }
+            RowPolicy::Open => writeln!(out, "    RowPolicy:\n      impl: OpenRowPolicy").unwrap(),
+        }
+        writeln!(out, "    plugins:\n").unwrap();
+        writeln!(out, "  AddrMapper:\n    impl: {}", self.mapping.name()).unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml_round_trip() {
+        let mut config = DramConfig::default();
+        config
+            .merge(&[
+                "MemorySystem.Controller.Scheduler.impl=BLISS".to_string(),
+                "MemorySystem.AddrMapper.impl=MOP4CLXOR".to_string(),
+                "MemorySystem.DRAM.org.rank=1".to_string(),
+            ])
+            .unwrap();
+        let mut parsed = DramConfig::default();
+        parsed.merge_yaml(&config.to_yaml()).unwrap();
+        assert_eq!(parsed, config);
+        assert!(config.to_yaml().contains("impl: BLISS"));
+    }
+
+    #[test]
+    fn unknown_key_and_value() {
+        let mut config = DramConfig::default();
+        assert!(matches!(
+            config.set("MemorySystem.DRAM.org.banks", "4"),
+            Err(ConfigError::UnknownKey(_))
+        ));
+        assert!(config.set("MemorySystem.Controller.Scheduler.impl", "FCFS").is_err());
+        assert!(config.set("MemorySystem.DRAM.org.rank", "two").is_err());
+        assert!(config.set("Frontend.clock_ratio", "2").is_err());
+        config.set("MemorySystem.clock_ratio", "1").unwrap();
+        assert_eq!(config, DramConfig::default());
+    }
+
+    #[test]
+    fn cap_needs_closed_policy() {
+        let mut config = DramConfig::default();
+        config.set("MemorySystem.Controller.RowPolicy.cap", "8").unwrap();
+        assert_eq!(config.controller.row_policy, RowPolicy::Closed { cap: 8 });
+        config.set("MemorySystem.Controller.RowPolicy.impl", "OpenRowPolicy").unwrap();
+        assert!(config.set("MemorySystem.Controller.RowPolicy.cap", "8").is_err());
+        assert_eq!(config.controller.row_policy, RowPolicy::Open);
+    }
+
+    #[test]
+    fn validate_rejects() {
+        for (key, value) in [
+            ("MemorySystem.DRAM.org.rank", "3"),
+            ("MemorySystem.DRAM.org.preset", "DDR4_9Gb_x8"),
+            ("MemorySystem.DRAM.timing.preset", "DDR4_9999Z"),
+            ("MemorySystem.Controller.RowPolicy.cap", "0"),
+        ] {
+            let mut config = DramConfig::default();
+            config.set(key, value).unwrap();
+            assert!(config.validate().is_err(), "{}={}", key, value);
+        }
+    }
+
+    #[test]
+    fn yaml_syntax_error() {
+        let mut config = DramConfig::default();
+        match config.merge_yaml("MemorySystem:\n  impl GenericDRAM\n") {
+            Err(ConfigError::Syntax { line, .. }) => assert_eq!(line, 2),
+            other => panic!("{:?}", other.err()),
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use memory_interface::trace::{TraceEvent, TraceReader, TraceRecord, TraceWriter};
+use memory_interface::RequestKind;
+use std::fmt::Write;
+use std::fs;
+
+// The trace formats we can read and write:
+//   inst       Ramulator's SimpleO3 traces, `BUBBLES LOAD [WRITEBACK]`, where
+//              BUBBLES counts the non-memory instructions before the load
+//   loadstore  Ramulator's LoadStore traces, `LD ADDR` or `ST ADDR`
+//   dramsim3   `ADDR READ|WRITE CYCLE`, R and W are read too
+//   memtrace   our own request log, binary or .csv, see memory_interface::trace
+//   champsim   ChampSim's input_instr records, uncompressed: up to four loads
+//              and two stores an instruction, address 0 meaning none
+// Addresses in text are read in decimal or 0x-prefixed hex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    Inst,
+    LoadStore,
+    DramSim3,
+    MemTrace,
+    ChampSim,
+}
+
+impl TraceFormat {
+    pub const ALL: [TraceFormat; 5] = [
+        TraceFormat::Inst,
+        TraceFormat::LoadStore,
+        TraceFormat::DramSim3,
+        TraceFormat::MemTrace,
+        TraceFormat::ChampSim,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        TraceFormat::ALL.into_iter().find(|format| format.name() == s)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TraceFormat::Inst => "inst",
+            TraceFormat::LoadStore => "loadstore",
+            TraceFormat::DramSim3 => "dramsim3",
+            TraceFormat::MemTrace => "memtrace",
+            TraceFormat::ChampSim => "champsim",
+        }
+    }
+}
+
+// What every format has in common. The cycle is whatever clock the format
+// keeps: instructions for inst and champsim traces, the line for loadstore
+// ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemOp {
+    pub cycle: u64,
+    pub addr: i64,
+    pub write: bool,
+}
+
+fn parse_addr(s: &str) -> Option<i64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(|a| a as i64),
+        None => s.parse().ok(),
+    }
+}
+
+// The meaningful lines of a text trace with their numbers, for errors.
+fn lines(text: &str) -> impl Iterator<Item = (usize, Vec<&str>)> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split_whitespace().collect::<Vec<_>>()))
+        .filter(|(_, tokens)| !tokens.is_empty() && !tokens[0].starts_with('#'))
+}
+
+fn read_inst(text: &str) -> Result<Vec<MemOp>, String> {
+    let mut ops = Vec::new();
+    let mut insts = 0u64;
+    for (n, tokens) in lines(text) {
+        let bubbles = tokens[0].parse::<u64>().ok();
+        let load = tokens.get(1).and_then(|a| parse_addr(a));
+        let writeback = tokens.get(2).map(|a| parse_addr(a));
+        let (Some(bubbles), Some(load), 2..=3) = (bubbles, load, tokens.len()) else {
+            return Err(format!("line {}: expected BUBBLES LOAD [WRITEBACK]", n));
+        };
+        insts += bubbles;
+        ops.push(MemOp {
+            cycle: insts,
+            addr: load,
+            write: false,
+        });
+        match writeback {
+            Some(Some(addr)) => ops.push(MemOp {
+                cycle: insts,
+                addr,
+                write: true,
+            }),
+            Some(None) => return Err(format!("line {}: bad writeback address", n)),
+            None => {}
+        }
+        insts += 1;
+    }
+    Ok(ops)
+}
+
+fn read_loadstore(text: &str) -> Result<Vec<MemOp>, String> {
+    lines(text)
+        .enumerate()
+        .map(|(i, (n, tokens))| {
+            let write = match tokens[0] {
+                "LD" => false,
+                "ST" => true,
+                _ => return Err(format!("line {}: expected LD or ST, got `{}`", n, tokens[0])),
+            };
+            match (tokens.get(1).and_then(|a| parse_addr(a)), tokens.len()) {
+                (Some(addr), 2) => Ok(MemOp {
+                    cycle: i as u64,
+                    addr,
+                    write,
+                }),
+                _ => Err(format!("line {}: expected LD|ST ADDR", n)),
+            }
+        })
+        .collect()
+}
+
+fn read_dramsim3(text: &str) -> Result<Vec<MemOp>, String> {
+    lines(text)
+        .map(|(n, tokens)| {
+            let write = match tokens.get(1).map(|t| t.to_ascii_uppercase()).as_deref() {
+                Some("READ") | Some("R") => false,
+                Some("WRITE") | Some("W") => true,
+                _ => return Err(format!("line {}: expected READ or WRITE", n)),
+            };
+            match (parse_addr(tokens[0]), tokens.get(2).and_then(|c| c.parse().ok()), tokens.len()) {
+                (Some(addr), Some(cycle), 3) => Ok(MemOp { cycle, addr, write }),
+                _ => Err(format!("line {}: expected ADDR READ|WRITE CYCLE", n)),
+            }
+        })
+        .collect()
+}
+
+// The requests the memory took, at the DRAM cycle it took them. Refused
+// attempts were retried and logged again, completions repeat their send.
+fn read_memtrace(path: &str) -> Result<Vec<MemOp>, String> {
+    let mut ops = Vec::new();
+    for record in TraceReader::open(path).map_err(|e| e.to_string())? {
+        let record = record.map_err(|e| e.to_string())?;
+        if record.event != TraceEvent::Sent {
+            continue;
+        }
+        let write = match record.kind {
+            RequestKind::Read => false,
+            RequestKind::Write => true,
+            _ => continue, // refreshes and row commands carry no data
+        };
+        ops.push(MemOp {
+            cycle: record.cycle,
+            addr: record.addr,
+            write,
+        });
+    }
+    Ok(ops)
+}
+
+// ChampSim's input_instr: ip, is_branch, branch_taken, two destination and
+// four source registers, two destination and four source memory addresses.
+const CHAMPSIM_RECORD: usize = 64;
+const CHAMPSIM_STORES: usize = 16; // offset of destination_memory
+const CHAMPSIM_LOADS: usize = 32; // and of source_memory
+// ChampSim's traces usually come xz-compressed
+const XZ_MAGIC: &[u8] = b"\xfd7zXZ\0";
+
+fn read_champsim(bytes: &[u8]) -> Result<Vec<MemOp>, String> {
+    if bytes.starts_with(XZ_MAGIC) {
+        return Err("the trace is xz-compressed, run xz -d on it first".to_string());
+    }
+    if !bytes.len().is_multiple_of(CHAMPSIM_RECORD) {
+        return Err(format!(
+            "{} bytes is not a whole number of {}-byte records",
+            bytes.len(),
+            CHAMPSIM_RECORD
+        ));
+    }
+    let mut ops = Vec::new();
+    for (i, record) in bytes.chunks(CHAMPSIM_RECORD).enumerate() {
+        let addr_at = |offset: usize| u64::from_le_bytes(record[offset..offset + 8].try_into().unwrap()) as i64;
+        for (start, count, write) in [(CHAMPSIM_LOADS, 4, false), (CHAMPSIM_STORES, 2, true)] {
+            for addr in (0..count).map(|j| addr_at(start + 8 * j)).filter(|a| *a != 0) {
+                ops.push(MemOp {
+                    cycle: i as u64,
+                    addr,
+                    write,
+                });
+            }
+        }
+    }
+    Ok(ops)
+}
+
+// One record per instruction, those without memory operations filling the
+// gaps; an op that finds its instruction's slots taken goes to the next one.
+fn write_champsim(ops: &[MemOp]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut record = [0u8; CHAMPSIM_RECORD];
+    let (mut loads, mut stores) = (0, 0);
+    let mut inst = 0u64; // the instruction of `record`
+    for op in ops {
+        if op.addr == 0 {
+            return Err(format!("ChampSim has no way to say address 0, at cycle {}", op.cycle));
+        }
+        let full = if op.write { stores == 2 } else { loads == 4 };
+        if op.cycle > inst || full {
+            out.extend_from_slice(&record);
+            record = [0; CHAMPSIM_RECORD];
+            (loads, stores) = (0, 0);
+            inst += 1;
+            // the instructions in between touch no memory
+            for _ in inst..op.cycle {
+                out.extend_from_slice(&[0; CHAMPSIM_RECORD]);
+            }
+            inst = inst.max(op.cycle);
+        }
+        let offset = if op.write {
+            stores += 1;
+            CHAMPSIM_STORES + 8 * (stores - 1)
+        } else {
+            loads += 1;
+            CHAMPSIM_LOADS + 8 * (loads - 1)
+        };
+        record[offset..offset + 8].copy_from_slice(&(op.addr as u64).to_le_bytes());
+    }
+    if !ops.is_empty() {
+        out.extend_from_slice(&record);
+    }
+    Ok(out)
+}
+
+pub fn read(format: TraceFormat, path: &str) -> Result<Vec<MemOp>, String> {
+    let ops = match format {
+        TraceFormat::MemTrace => read_memtrace(path),
+        TraceFormat::ChampSim => fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| read_champsim(&bytes)),
+        _ => fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| match format {
+                TraceFormat::Inst => read_inst(&text),
+                TraceFormat::LoadStore => read_loadstore(&text),
+                TraceFormat::DramSim3 => read_dramsim3(&text),
+                TraceFormat::MemTrace | TraceFormat::ChampSim => unreachable!(),
+            }),
+    };
+    ops.map_err(|e| format!("{}: {}", path, e))
+}
+
+// An inst trace only writes back on the line of a load, so each write joins
+// the load before it, and the cycles between loads become bubbles.
+fn write_inst(ops: &[MemOp]) -> Result<String, String> {
+    let mut out = String::new();
+    let mut insts = 0u64;
+    let mut line: Option<(u64, i64, Option<i64>)> = None;
+    for op in ops {
+        if op.write {
+            match line.as_mut() {
+                Some((_, _, writeback @ None)) => *writeback = Some(op.addr),
+                _ => {
+                    return Err(format!(
+                        "the write to {} at cycle {} follows no load to write back with",
+                        op.addr, op.cycle
+                    ))
+                }
+            }
+            continue;
+        }
+        if let Some(line) = line.take() {
+            push_inst(&mut out, line);
+        }
+        let bubbles = op.cycle.saturating_sub(insts);
+        insts += bubbles + 1;
+        line = Some((bubbles, op.addr, None));
+    }
+    if let Some(line) = line {
+        push_inst(&mut out, line);
+    }
+    Ok(out)
+}
+
+fn push_inst(out: &mut String, (bubbles, load, writeback): (u64, i64, Option<i64>)) {
+    match writeback {
+        Some(addr) => writeln!(out, "{} {} {}", bubbles, load, addr).unwrap(),
+        None => writeln!(out, "{} {}", bubbles, load).unwrap(),
+    }
+}
+
+fn write_memtrace(path: &str, ops: &[MemOp]) -> Result<(), String> {
+    let mut writer = TraceWriter::create(path).map_err(|e| e.to_string())?;
+    for (id, op) in ops.iter().enumerate() {
+        let record = TraceRecord {
+            event: TraceEvent::Sent,
+            stamp: op.cycle,
+            cycle: op.cycle,
+            addr: op.addr,
+            kind: if op.write { RequestKind::Write } else { RequestKind::Read },
+            id: id as u64,
+            latency: 0,
+        };
+        writer.record(&record).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())
+}
+
+pub fn write(format: TraceFormat, path: &str, ops: &[MemOp]) -> Result<(), String> {
+    let text = match format {
+        TraceFormat::MemTrace => return write_memtrace(path, ops).map_err(|e| format!("{}: {}", path, e)),
+        TraceFormat::ChampSim => {
+            let bytes = write_champsim(ops).map_err(|e| format!("{}: {}", path, e))?;
+            return fs::write(path, bytes).map_err(|e| format!("{}: {}", path, e));
+        }
+        TraceFormat::Inst => write_inst(ops).map_err(|e| format!("{}: {}", path, e))?,
+        TraceFormat::LoadStore => ops.iter().fold(String::new(), |mut out, op| {
+            writeln!(out, "{} {}", if op.write { "ST" } else { "LD" }, op.addr).unwrap();
+            out
+        }),
+        TraceFormat::DramSim3 => ops.iter().fold(String::new(), |mut out, op| {
+            let kind = if op.write { "WRITE" } else { "READ" };
+            writeln!(out, "0x{:x} {} {}", op.addr, kind, op.cycle).unwrap();
+            out
+        }),
+    };
+    fs::write(path, text).map_err(|e| format!("{}: {}", path, e))
+}
+
+// Returns how many requests were carried over.
+pub fn convert(from: TraceFormat, input: &str, to: TraceFormat, output: &str) -> Result<usize, String> {
+    let ops = read(from, input)?;
+    write(to, output, &ops)?;
+    Ok(ops.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(cycle: u64, addr: i64, write: bool) -> MemOp {
+        MemOp { cycle, addr, write }
+    }
+
+    #[test]
+    fn inst_round_trip() {
+        let text = "3 20734016\n0 64\n# a comment\n\n8 20841280 20841280\n";
+        let ops = read_inst(text).unwrap();
+        assert_eq!(
+            ops,
+            vec![
+                op(3, 20734016, false),
+                op(4, 64, false),
+                op(13, 20841280, false),
+                op(13, 20841280, true),
+            ]
+        );
+        assert_eq!(write_inst(&ops).unwrap(), "3 20734016\n0 64\n8 20841280 20841280\n");
+    }
+
+    #[test]
+    fn inst_errors() {
+        assert!(read_inst("3\n").unwrap_err().starts_with("line 1:"));
+        assert!(read_inst("1 64\nx 64\n").unwrap_err().starts_with("line 2:"));
+        assert!(read_inst("1 64 0xzz\n").unwrap_err().contains("writeback"));
+        assert!(read_inst("1 64 128 256\n").is_err());
+    }
+
+    #[test]
+    fn writeback_needs_a_load() {
+        assert!(write_inst(&[op(0, 64, true)]).unwrap_err().contains("no load"));
+        // a load has room for one writeback only
+        let ops = [op(0, 64, false), op(0, 128, true), op(0, 192, true)];
+        assert!(write_inst(&ops).is_err());
+    }
+
+    #[test]
+    fn dramsim3() {
+        let ops = read_dramsim3("0x40 READ 0\n0X80 write 5\n192 R 7\n256 W 9\n").unwrap();
+        assert_eq!(
+            ops,
+            vec![op(0, 64, false), op(5, 128, true), op(7, 192, false), op(9, 256, true)]
+        );
+        assert!(read_dramsim3("0x40 FETCH 0\n").unwrap_err().starts_with("line 1:"));
+        assert!(read_dramsim3("0x40 READ\n").is_err());
+        assert!(read_dramsim3("0x40 READ 1 2\n").is_err());
+        assert!(read_dramsim3("0xg0 READ 1\n").is_err());
+    }
+
+    #[test]
+    fn loadstore() {
+        let ops = read_loadstore("LD 64\nST 0x80\n").unwrap();
+        assert_eq!(ops, vec![op(0, 64, false), op(1, 128, true)]);
+        assert!(read_loadstore("LD\n").is_err());
+        assert!(read_loadstore("MOV 64\n").is_err());
+    }
+
+    #[test]
+    fn champsim_round_trip() {
+        let ops = vec![
+            op(0, 64, false),
+            op(0, 128, true),
+            op(3, 192, false),
+            op(3, 256, false),
+            op(3, 320, false),
+            op(3, 384, false),
+            op(5, 448, true),
+        ];
+        let bytes = write_champsim(&ops).unwrap();
+        assert_eq!(bytes.len(), 6 * CHAMPSIM_RECORD);
+        assert_eq!(read_champsim(&bytes).unwrap(), ops);
+    }
+
+    #[test]
+    fn champsim_spills_and_errors() {
+        // a fifth load of one instruction moves to the next
+        let ops: Vec<MemOp> = (1..=5).map(|i| op(0, 64 * i, false)).collect();
+        let read = read_champsim(&write_champsim(&ops).unwrap()).unwrap();
+        assert_eq!(read[4], op(1, 320, false));
+        assert!(write_champsim(&[op(0, 0, false)]).is_err());
+        assert!(read_champsim(&[0; CHAMPSIM_RECORD + 1]).is_err());
+        assert!(read_champsim(XZ_MAGIC).unwrap_err().contains("xz"));
+    }
+}
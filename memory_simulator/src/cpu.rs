@@ -0,0 +1,324 @@
+use super::config::DramConfig;
+use super::convert::{self, MemOp, TraceFormat};
+use memory_interface::trace::TraceWriter;
+use memory_interface::{BackendKind, MemoryError, MemoryInterface, Request, RequestKind};
+use std::collections::VecDeque;
+use std::ffi::c_void;
+
+// Give up once nothing has retired for this many core cycles.
+const STALL_LIMIT: u64 = 1_000_000;
+
+// A simple out-of-order core in the manner of Ramulator's SimpleO3: each
+// cycle it retires up to `issue_width` finished instructions from the head
+// of the ROB and issues up to as many new ones. The trace's bubbles are
+// instructions that finish at once, its loads finish when memory answers
+// and a load's writeback is sent alongside it without taking a slot.
+#[derive(Clone)]
+pub struct CpuOptions {
+    pub trace: String,
+    pub format: TraceFormat, // for anything but inst, an op's cycle is its instruction
+    pub rob_size: usize,
+    pub issue_width: usize,
+    pub core_ratio: usize,  // core cycles per memory cycle
+    pub insts: Option<u64>, // replay the trace until this many have issued
+    pub dram: DramConfig,
+    pub backend: Option<BackendKind>,
+    pub mem_trace: Option<String>,
+}
+
+impl Default for CpuOptions {
+    fn default() -> Self {
+        CpuOptions {
+            trace: "configs/example_inst.trace".to_string(),
+            format: TraceFormat::Inst,
+            rob_size: 128,
+            issue_width: 4,
+            core_ratio: 3, // about 3.6 GHz against DDR4-2400
+            insts: None,
+            dram: DramConfig::default(),
+            backend: None,
+            mem_trace: None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CpuStats {
+    pub insts: u64,
+    pub cycles: u64,
+    pub mem_cycles: u64,
+    pub loads: u64,
+    pub writes: u64,
+    pub load_latencies: Vec<u64>, // memory cycles
+    pub rob_full: u64,            // cycles issue stopped for each reason
+    pub queue_full: u64,
+}
+
+impl CpuStats {
+    pub fn ipc(&self) -> f64 {
+        self.insts as f64 / self.cycles.max(1) as f64
+    }
+}
+
+// The instructions in flight, oldest first, whether each is done. The
+// oldest has sequence number `head`. Shared with the callback.
+#[derive(Default)]
+struct Rob {
+    head: u64,
+    entries: VecDeque<bool>,
+    load_latencies: Vec<u64>,
+}
+
+impl Rob {
+    fn tail(&self) -> u64 {
+        self.head + self.entries.len() as u64
+    }
+}
+
+// Each load carries its own ctx naming its ROB entry.
+struct LoadCtx {
+    rob: *mut Rob,
+    seq: u64,
+}
+
+fn cpu_callback(req: &Request, ctx: *mut c_void) {
+    if ctx.is_null() {
+        return; // a write, nothing waits on it
+    }
+    let load = unsafe { Box::from_raw(ctx as *mut LoadCtx) };
+    let rob: &mut Rob = unsafe { &mut *load.rob };
+    // a load can't retire before it is done, so its entry is still there
+    rob.entries[(load.seq - rob.head) as usize] = true;
+    rob.load_latencies.push((req.depart - req.arrive).max(0) as u64);
+}
+
+fn send(mem: &MemoryInterface, addr: i64, kind: RequestKind, ctx: *mut c_void) -> Result<bool, String> {
+    match unsafe { mem.send_request(addr, kind, cpu_callback, ctx) } {
+        Ok(()) => Ok(true),
+        Err(MemoryError::QueueFull) => Ok(false),
+        Err(e) => Err(format!("a {} of {}: {}", kind, addr, e)),
+    }
+}
+
+fn send_load(mem: &MemoryInterface, addr: i64, rob: &mut Rob) -> Result<bool, String> {
+    let seq = rob.tail();
+    let ctx = Box::into_raw(Box::new(LoadCtx { rob, seq })) as *mut c_void;
+    let sent = send(mem, addr, RequestKind::Read, ctx);
+    if sent != Ok(true) {
+        // refused, the callback won't run
+        drop(unsafe { Box::from_raw(ctx as *mut LoadCtx) });
+    }
+    sent
+}
+
+fn run(opts: &CpuOptions, ops: &[MemOp]) -> Result<CpuStats, String> {
+    let backend = opts.backend.unwrap_or_else(BackendKind::preferred);
+    let mem = unsafe { MemoryInterface::open_backend(backend) }.map_err(|e| e.to_string())?;
+    println!("Memory backend: {}", mem.backend_name());
+    mem.init_from_string(&opts.dram.to_yaml()).map_err(|e| e.to_string())?;
+    if let Some(path) = &opts.mem_trace {
+        mem.set_trace(TraceWriter::create(path).map_err(|e| format!("{}: {}", path, e))?);
+    }
+
+    let mut rob = Rob::default();
+    let writes = std::ptr::null_mut();
+    let mut stats = CpuStats::default();
+    let mut next = 0; // the op to issue
+    let mut offset = 0; // added to its cycle, grows each replay
+    let mut position = 0; // instructions issued
+    let mut last_retire = 0;
+    loop {
+        mem.set_stamp(stats.cycles);
+        let mut retired = 0;
+        while retired < opts.issue_width && rob.entries.front() == Some(&true) {
+            rob.entries.pop_front();
+            rob.head += 1;
+            retired += 1;
+        }
+        stats.insts += retired as u64;
+        if retired > 0 {
+            last_retire = stats.cycles;
+        }
+
+        let mut issued = 0;
+        while issued < opts.issue_width {
+            if next == ops.len() && opts.insts.is_some_and(|n| position < n) {
+                next = 0;
+                offset = position;
+            }
+            let Some(op) = ops.get(next).filter(|_| opts.insts.is_none_or(|n| position < n)) else {
+                break;
+            };
+            let at = op.cycle + offset;
+            if op.write && at < position {
+                // the writeback of the load before it
+                if !send(&mem, op.addr, RequestKind::Write, writes)? {
+                    stats.queue_full += 1;
+                    break;
+                }
+                stats.writes += 1;
+                next += 1;
+                continue;
+            }
+            if rob.entries.len() >= opts.rob_size {
+                stats.rob_full += 1;
+                break;
+            }
+            if position < at {
+                rob.entries.push_back(true); // a bubble
+            } else {
+                let sent = if op.write {
+                    send(&mem, op.addr, RequestKind::Write, writes)?
+                } else {
+                    send_load(&mem, op.addr, &mut rob)?
+                };
+                if !sent {
+                    stats.queue_full += 1;
+                    break;
+                }
+                if op.write {
+                    stats.writes += 1;
+                    rob.entries.push_back(true); // a store, done once sent
+                } else {
+                    stats.loads += 1;
+                    rob.entries.push_back(false);
+                }
+                next += 1;
+            }
+            position += 1;
+            issued += 1;
+        }
+
+        let drained = next == ops.len() || opts.insts.is_some_and(|n| position >= n);
+        if drained && rob.entries.is_empty() {
+            break;
+        }
+        if stats.cycles - last_retire > STALL_LIMIT {
+            return Err(format!(
+                "nothing retired for {} cycles, {} instructions in flight",
+                STALL_LIMIT,
+                rob.entries.len()
+            ));
+        }
+        stats.cycles += 1;
+        if stats.cycles % opts.core_ratio as u64 == 0 {
+            mem.frontend_tick().map_err(|e| e.to_string())?;
+            mem.memory_tick().map_err(|e| e.to_string())?;
+            stats.mem_cycles += 1;
+        }
+    }
+    if let Some(path) = &opts.mem_trace {
+        match mem.take_trace() {
+            Ok(Some(writer)) => println!("Memory trace: {} records in {}", writer.records, path),
+            Ok(None) => println!("Memory trace {} is incomplete", path),
+            Err(e) => println!("Failed to write memory trace {}: {}", path, e),
+        }
+    }
+    stats.load_latencies = std::mem::take(&mut rob.load_latencies);
+    Ok(stats)
+}
+
+pub fn simulate(opts: &CpuOptions) -> Result<CpuStats, String> {
+    let ops = convert::read(opts.format, &opts.trace)?;
+    if ops.is_empty() {
+        return Err(format!("{}: no memory operations", opts.trace));
+    }
+    println!(
+        "Core: ROB {}, issue width {}, {} core cycles per memory cycle",
+        opts.rob_size, opts.issue_width, opts.core_ratio
+    );
+    println!("Trace: {} ({}), {} memory operations", opts.trace, opts.format.name(), ops.len());
+    let stats = run(opts, &ops)?;
+    let latencies = &stats.load_latencies;
+    println!(
+        "Retired {} instructions in {} cycles ({} memory cycles), IPC {:.3}",
+        stats.insts,
+        stats.cycles,
+        stats.mem_cycles,
+        stats.ipc()
+    );
+    println!(
+        "Loads: {}, avg latency {:.1} memory cycles, max {}",
+        stats.loads,
+        latencies.iter().sum::<u64>() as f64 / latencies.len().max(1) as f64,
+        latencies.iter().copied().max().unwrap_or(0)
+    );
+    println!("Writes: {}", stats.writes);
+    println!(
+        "Issue stalls: {} cycles on a full ROB, {} on a full memory queue",
+        stats.rob_full, stats.queue_full
+    );
+    Ok(stats)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    fn mock(rob_size: usize, issue_width: usize) -> CpuOptions {
+        CpuOptions {
+            rob_size,
+            issue_width,
+            core_ratio: 1,
+            backend: Some(BackendKind::Mock),
+            ..CpuOptions::default()
+        }
+    }
+
+    fn load(cycle: u64, addr: i64) -> MemOp {
+        MemOp { cycle, addr, write: false }
+    }
+
+    #[test]
+    fn rob_full_stalls() {
+        let ops: Vec<MemOp> = (0..16).map(|i| load(i, 64 * i as i64)).collect();
+        let small = run(&mock(4, 4), &ops).unwrap();
+        assert!(small.rob_full > 0);
+        assert_eq!((small.insts, small.loads, small.load_latencies.len()), (16, 16, 16));
+        let large = run(&mock(64, 4), &ops).unwrap();
+        assert_eq!(large.rob_full, 0);
+        assert_eq!(large.insts, 16);
+        assert!(large.cycles < small.cycles);
+    }
+
+    #[test]
+    fn issue_width() {
+        // 99 bubbles and a load, which issues once the bubbles have and
+        // retires the mock's 36 cycles later
+        let ops = [load(99, 0)];
+        let narrow = run(&mock(128, 1), &ops).unwrap();
+        assert_eq!((narrow.insts, narrow.cycles), (100, 99 + 36));
+        let wide = run(&mock(128, 4), &ops).unwrap();
+        assert_eq!((wide.insts, wide.cycles), (100, 24 + 36));
+        assert_eq!(wide.rob_full + wide.queue_full, 0);
+    }
+
+    #[test]
+    fn load_completes_its_own_entry() {
+        let mem = MemoryInterface::mock();
+        mem.init_from_string(&DramConfig::default().to_yaml()).unwrap();
+        let mut rob = Rob {
+            head: 7,
+            ..Rob::default()
+        };
+        // two loads of the same address around a bubble
+        assert!(send_load(&mem, 64, &mut rob).unwrap());
+        rob.entries.push_back(false);
+        rob.entries.push_back(true);
+        for _ in 0..4 {
+            mem.memory_tick().unwrap();
+        }
+        assert!(send_load(&mem, 64, &mut rob).unwrap());
+        rob.entries.push_back(false);
+        // they finish 36 cycles after they were sent
+        for _ in 4..36 {
+            mem.memory_tick().unwrap();
+        }
+        assert_eq!(rob.entries, [true, true, false]);
+        for _ in 36..40 {
+            mem.memory_tick().unwrap();
+        }
+        assert_eq!(rob.entries, [true, true, true]);
+        assert_eq!(rob.load_latencies, [36, 36]);
+    }
+}
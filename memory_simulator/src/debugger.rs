@@ -0,0 +1,169 @@
+use super::clock::cyclize;
+use super::simulator::Simulator;
+use super::watch::{WatchAction, WatchSpec};
+use memory_interface::MemoryError;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+commands:
+  step [N]            run N cycles (default 1)
+  until CYCLE         run until the given cycle
+  continue            run until a breakpoint, a watch or the end
+  break MODULE        stop after MODULE triggers
+  watch SPEC          stop on a matching array write or FIFO push,
+                      SPEC is TARGET[ADDR] [OP VALUE] [log|break]
+  delete NAME         remove a breakpoint, or a watch as listed by info
+  print FIFO          dump a FIFO and its pending push/pop
+  print ARRAY[ADDR]   show an array word
+  info                show cycle, breakpoints and watches
+  save PATH           write a checkpoint
+  quit";
+
+struct Debugger {
+    breaks: Vec<String>,
+    finished: bool,
+}
+
+fn parse_element(spec: &str) -> Option<(&str, usize)> {
+    let (name, rest) = spec.split_once('[')?;
+    let addr = rest.strip_suffix(']')?.parse().ok()?;
+    Some((name, addr))
+}
+
+impl Debugger {
+    // Run one cycle, returning the reason to stop if any.
+    fn step(&mut self, sim: &mut Simulator) -> Result<Option<String>, MemoryError> {
+        if self.finished || !sim.step()? {
+            self.finished = true;
+            return Ok(Some("simulation finished".to_string()));
+        }
+        let mut reasons = Vec::new();
+        for name in self.breaks.iter() {
+            if sim.triggered(name) {
+                reasons.push(format!("{} triggered", name));
+            }
+        }
+        if sim.watch_break() {
+            reasons.push("watchpoint hit".to_string());
+        }
+        if reasons.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(reasons.join(", ")))
+        }
+    }
+
+    fn run(&mut self, sim: &mut Simulator, until: impl Fn(&Simulator) -> bool) -> Result<(), MemoryError> {
+        loop {
+            if let Some(reason) = self.step(sim)? {
+                println!("{}: {}", cyclize(sim.stamp), reason);
+                return Ok(());
+            }
+            if until(sim) {
+                println!("{}", cyclize(sim.stamp));
+                return Ok(());
+            }
+        }
+    }
+
+    fn info(&self, sim: &Simulator) {
+        println!("{}, {} cycles evaluated", cyclize(sim.stamp), sim.evaluated);
+        for slot in sim.modules.iter() {
+            println!(
+                "  module {:<16} triggered: {:<5} next event: {}",
+                slot.module.name(),
+                slot.triggered,
+                slot.event.front().map_or("-".to_string(), |s| cyclize(*s))
+            );
+        }
+        for name in self.breaks.iter() {
+            println!("  break {}", name);
+        }
+        for label in sim.watch_labels() {
+            println!("  watch {}", label);
+        }
+    }
+
+    // Returns false when the user asked to quit.
+    fn command(&mut self, sim: &mut Simulator, line: &str) -> Result<bool, MemoryError> {
+        if line.is_empty() {
+            return Ok(true);
+        }
+        let (cmd, arg) = match line.split_once(char::is_whitespace) {
+            Some((cmd, arg)) => (cmd, Some(arg.trim())),
+            None => (line, None),
+        };
+        match (cmd, arg) {
+            ("step" | "s", n) => {
+                let n = n.and_then(|n| n.parse().ok()).unwrap_or(1);
+                let target = sim.evaluated + n;
+                self.run(sim, |sim| sim.evaluated >= target)?;
+            }
+            ("until" | "u", Some(cycle)) => match cycle.parse::<usize>() {
+                Ok(cycle) => self.run(sim, |sim| sim.clock.cycle(sim.stamp) >= cycle)?,
+                Err(_) => println!("invalid cycle `{}`", cycle),
+            },
+            ("continue" | "c", None) => self.run(sim, |_| false)?,
+            ("break" | "b", Some(name)) => {
+                if sim.modules.iter().any(|m| m.module.name() == name) {
+                    self.breaks.push(name.to_string());
+                } else {
+                    println!("no module named {}", name);
+                }
+            }
+            ("watch" | "w", Some(spec)) => {
+                let added = WatchSpec::parse(spec, WatchAction::Break)
+                    .and_then(|spec| sim.add_watch(spec));
+                if let Err(e) = added {
+                    println!("{}", e);
+                }
+            }
+            ("delete" | "d", Some(name)) => {
+                let before = self.breaks.len();
+                self.breaks.retain(|b| b != name);
+                if self.breaks.len() == before && !sim.remove_watch(name) {
+                    println!("no breakpoint or watch `{}`", name);
+                }
+            }
+            ("print" | "p", Some(name)) => {
+                if let Some((array, addr)) = parse_element(name) {
+                    match sim.array_word(array, addr) {
+                        Some(value) => println!("{} = {}", name, value),
+                        None => println!("no array element {}", name),
+                    }
+                } else if !sim.print_fifo(name) {
+                    println!("no FIFO named {}", name);
+                }
+            }
+            ("info" | "i", None) => self.info(sim),
+            ("save", Some(path)) => match sim.save_checkpoint(path) {
+                Ok(()) => println!("Saved checkpoint {} at {}", path, cyclize(sim.stamp)),
+                Err(e) => println!("Failed to save checkpoint {}: {}", path, e),
+            },
+            ("quit" | "q", None) => return Ok(false),
+            _ => println!("{}", HELP),
+        }
+        Ok(true)
+    }
+}
+
+pub fn repl(sim: &mut Simulator) -> Result<(), MemoryError> {
+    let mut dbg = Debugger {
+        breaks: Vec::new(),
+        finished: false,
+    };
+    println!("{} (type `help` for commands)", cyclize(sim.stamp));
+    let stdin = io::stdin();
+    loop {
+        print!("(sim) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        if !dbg.command(sim, line.trim())? {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+// Hashes every event of a run with FNV-1a, which unlike DefaultHasher is
+// stable across toolchains, optionally keeping the lines for diffing.
+pub struct EventLog {
+    hash: u64,
+    count: usize,
+    lines: Option<Vec<String>>,
+}
+
+impl EventLog {
+    pub fn new(keep_lines: bool) -> Self {
+        EventLog {
+            hash: 0xcbf29ce484222325,
+            count: 0,
+            lines: if keep_lines { Some(Vec::new()) } else { None },
+        }
+    }
+
+    pub fn record(&mut self, stamp: usize, event: String) {
+        let line = format!("{} {}", stamp, event);
+        for byte in line.bytes().chain(std::iter::once(b'\n')) {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+        self.count += 1;
+        if let Some(lines) = &mut self.lines {
+            lines.push(line);
+        }
+    }
+
+    // Carry on from where a checkpointed run's log stopped, its lines are lost.
+    pub fn resume(&mut self, hash: u64, count: usize) {
+        self.hash = hash;
+        self.count = count;
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn lines(&self) -> &[String] {
+        self.lines.as_deref().unwrap_or(&[])
+    }
+}
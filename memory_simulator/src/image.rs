@@ -0,0 +1,444 @@
+use super::runtime::{Array, ValueCastTo};
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::fs::read_to_string;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    ReadMemH, // verilog $readmemh text
+    ReadMemB, // verilog $readmemb text
+    IntelHex,
+    BinaryLE,
+    BinaryBE,
+}
+
+impl ImageFormat {
+    pub const ALL: [ImageFormat; 5] = [
+        ImageFormat::ReadMemH,
+        ImageFormat::ReadMemB,
+        ImageFormat::IntelHex,
+        ImageFormat::BinaryLE,
+        ImageFormat::BinaryBE,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        ImageFormat::ALL.into_iter().find(|format| format.name() == s)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageFormat::ReadMemH => "readmemh",
+            ImageFormat::ReadMemB => "readmemb",
+            ImageFormat::IntelHex => "ihex",
+            ImageFormat::BinaryLE => "bin",
+            ImageFormat::BinaryBE => "bin-be",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(std::io::Error),
+    Syntax { line: usize, msg: String },
+    OutOfRange { line: usize, addr: usize, size: usize },
+    Truncated { len: usize, word: usize },
+    TooLarge { words: usize, size: usize },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Io(e) => write!(f, "{}", e),
+            ImageError::Syntax { line, msg } => write!(f, "line {}: {}", line, msg),
+            ImageError::OutOfRange { line, addr, size } => write!(
+                f,
+                "line {}: address {:#x} is out of range for an array of {} words",
+                line, addr, size
+            ),
+            ImageError::Truncated { len, word } => write!(
+                f,
+                "image is {} bytes, not a multiple of the {}-byte word size",
+                len, word
+            ),
+            ImageError::TooLarge { words, size } => write!(
+                f,
+                "image has {} words but the array only holds {}",
+                words, size
+            ),
+        }
+    }
+}
+
+impl Error for ImageError {}
+
+impl From<std::io::Error> for ImageError {
+    fn from(e: std::io::Error) -> Self {
+        ImageError::Io(e)
+    }
+}
+
+fn syntax(line: usize, msg: String) -> ImageError {
+    ImageError::Syntax { line, msg }
+}
+
+fn ihex_record(kind: u8, offset: u16, data: &[u8]) -> String {
+    let mut raw = vec![data.len() as u8, (offset >> 8) as u8, offset as u8, kind];
+    raw.extend_from_slice(data);
+    let sum = raw.iter().fold(0u8, |a, b| a.wrapping_add(*b));
+    raw.push(sum.wrapping_neg());
+    let mut out = String::from(":");
+    for byte in raw {
+        write!(out, "{:02X}", byte).unwrap();
+    }
+    out.push('\n');
+    out
+}
+
+// Image I/O treats every element as a word of size_of::<T>() bytes.
+impl<T> Array<T>
+where
+    T: Sized + Default + Clone + ValueCastTo<u64>,
+    u64: ValueCastTo<T>,
+{
+    fn word_bytes() -> usize {
+        std::mem::size_of::<T>()
+    }
+
+    fn word_mask() -> u64 {
+        if Self::word_bytes() >= 8 {
+            u64::MAX
+        } else {
+            (1u64 << (8 * Self::word_bytes())) - 1
+        }
+    }
+
+    pub fn load(&mut self, path: &str, format: ImageFormat) -> Result<(), ImageError> {
+        match format {
+            ImageFormat::ReadMemH => self.load_readmem(&read_to_string(path)?, 16),
+            ImageFormat::ReadMemB => self.load_readmem(&read_to_string(path)?, 2),
+            ImageFormat::IntelHex => self.load_ihex(&read_to_string(path)?),
+            ImageFormat::BinaryLE => self.load_binary(&fs::read(path)?, false),
+            ImageFormat::BinaryBE => self.load_binary(&fs::read(path)?, true),
+        }
+    }
+
+    pub fn dump(&self, path: &str, format: ImageFormat) -> Result<(), ImageError> {
+        let image = match format {
+            ImageFormat::ReadMemH => self.dump_readmem(16).into_bytes(),
+            ImageFormat::ReadMemB => self.dump_readmem(2).into_bytes(),
+            ImageFormat::IntelHex => self.dump_ihex().into_bytes(),
+            ImageFormat::BinaryLE => self.dump_binary(false),
+            ImageFormat::BinaryBE => self.dump_binary(true),
+        };
+        fs::write(path, image)?;
+        Ok(())
+    }
+
+    fn load_readmem(&mut self, text: &str, radix: u32) -> Result<(), ImageError> {
+        let mut idx = 0;
+        let mut in_comment = false;
+        let mut last_line = 0;
+        for (n, line) in text.lines().enumerate() {
+            let line_no = n + 1;
+            last_line = line_no;
+            // strip // and /* */ comments, the latter may span lines
+            let mut code = String::new();
+            let mut rest = line;
+            loop {
+                if in_comment {
+                    match rest.find("*/") {
+                        Some(end) => {
+                            rest = &rest[end + 2..];
+                            in_comment = false;
+                        }
+                        None => break,
+                    }
+                    continue;
+                }
+                match (rest.find("//"), rest.find("/*")) {
+                    (Some(l), b) if b.is_none_or(|b| l < b) => {
+                        code.push_str(&rest[..l]);
+                        break;
+                    }
+                    (_, Some(b)) => {
+                        code.push_str(&rest[..b]);
+                        code.push(' ');
+                        rest = &rest[b + 2..];
+                        in_comment = true;
+                    }
+                    _ => {
+                        code.push_str(rest);
+                        break;
+                    }
+                }
+            }
+
+            for token in code.split_whitespace() {
+                let token = token.replace('_', "");
+                if let Some(addr) = token.strip_prefix('@') {
+                    idx = usize::from_str_radix(addr, 16)
+                        .map_err(|_| syntax(line_no, format!("invalid address `{}`", token)))?;
+                    continue;
+                }
+                if idx >= self.payload.len() {
+                    return Err(ImageError::OutOfRange {
+                        line: line_no,
+                        addr: idx,
+                        size: self.payload.len(),
+                    });
+                }
+                // x and z digits have no two-state meaning, load them as zero
+                let digits: String = token
+                    .chars()
+                    .map(|c| if "xXzZ?".contains(c) { '0' } else { c })
+                    .collect();
+                let value = u64::from_str_radix(&digits, radix)
+                    .map_err(|_| syntax(line_no, format!("invalid word `{}`", token)))?;
+                if value & !Self::word_mask() != 0 {
+                    return Err(syntax(
+                        line_no,
+                        format!("`{}` does not fit in {} bits", token, 8 * Self::word_bytes()),
+                    ));
+                }
+                self.payload[idx] = ValueCastTo::<T>::cast(&value);
+                idx += 1;
+            }
+        }
+        if in_comment {
+            return Err(syntax(last_line, "unterminated block comment".to_string()));
+        }
+        Ok(())
+    }
+
+    fn set_byte(&mut self, line: usize, byte_addr: usize, byte: u8) -> Result<(), ImageError> {
+        let idx = byte_addr / Self::word_bytes();
+        if idx >= self.payload.len() {
+            return Err(ImageError::OutOfRange {
+                line,
+                addr: idx,
+                size: self.payload.len(),
+            });
+        }
+        let shift = 8 * (byte_addr % Self::word_bytes());
+        let word = ValueCastTo::<u64>::cast(&self.payload[idx]);
+        let word = (word & !(0xff << shift)) | ((byte as u64) << shift);
+        self.payload[idx] = ValueCastTo::<T>::cast(&word);
+        Ok(())
+    }
+
+    // Intel HEX addresses bytes, words are assembled little-endian.
+    fn load_ihex(&mut self, text: &str) -> Result<(), ImageError> {
+        let mut base = 0;
+        for (n, line) in text.lines().enumerate() {
+            let line_no = n + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let record = line
+                .strip_prefix(':')
+                .ok_or_else(|| syntax(line_no, "record does not start with `:`".to_string()))?;
+            if !record.is_ascii() || record.len() % 2 != 0 || record.len() < 10 {
+                return Err(syntax(line_no, "malformed record".to_string()));
+            }
+            let raw = (0..record.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&record[i..i + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| syntax(line_no, "invalid hex digit".to_string()))?;
+            let len = raw[0] as usize;
+            if raw.len() != len + 5 {
+                return Err(syntax(
+                    line_no,
+                    format!("record declares {} data bytes but has {}", len, raw.len() - 5),
+                ));
+            }
+            if raw.iter().fold(0u8, |a, b| a.wrapping_add(*b)) != 0 {
+                return Err(syntax(line_no, "checksum mismatch".to_string()));
+            }
+            let offset = ((raw[1] as usize) << 8) | raw[2] as usize;
+            let data = &raw[4..4 + len];
+            match raw[3] {
+                0x00 => {
+                    for (i, byte) in data.iter().enumerate() {
+                        self.set_byte(line_no, base + offset + i, *byte)?;
+                    }
+                }
+                0x01 => return Ok(()),
+                0x02 | 0x04 if len == 2 => {
+                    let upper = ((data[0] as usize) << 8) | data[1] as usize;
+                    base = if raw[3] == 0x02 { upper << 4 } else { upper << 16 };
+                }
+                // start addresses mean nothing for a memory image
+                0x03 | 0x05 => {}
+                kind => {
+                    return Err(syntax(
+                        line_no,
+                        format!("unsupported record type {:02X}", kind),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn load_binary(&mut self, image: &[u8], big_endian: bool) -> Result<(), ImageError> {
+        let word = Self::word_bytes();
+        if !image.len().is_multiple_of(word) {
+            return Err(ImageError::Truncated {
+                len: image.len(),
+                word,
+            });
+        }
+        if image.len() / word > self.payload.len() {
+            return Err(ImageError::TooLarge {
+                words: image.len() / word,
+                size: self.payload.len(),
+            });
+        }
+        for (idx, chunk) in image.chunks(word).enumerate() {
+            let value = if big_endian {
+                chunk.iter().fold(0u64, |a, b| (a << 8) | *b as u64)
+            } else {
+                chunk.iter().rev().fold(0u64, |a, b| (a << 8) | *b as u64)
+            };
+            self.payload[idx] = ValueCastTo::<T>::cast(&value);
+        }
+        Ok(())
+    }
+
+    fn dump_readmem(&self, radix: u32) -> String {
+        let mut out = String::from("@0\n");
+        for value in self.payload.iter() {
+            let bits = ValueCastTo::<u64>::cast(value) & Self::word_mask();
+            if radix == 16 {
+                writeln!(out, "{:01$x}", bits, 2 * Self::word_bytes()).unwrap();
+            } else {
+                writeln!(out, "{:01$b}", bits, 8 * Self::word_bytes()).unwrap();
+            }
+        }
+        out
+    }
+
+    fn dump_ihex(&self) -> String {
+        let image = self.dump_binary(false);
+        let mut out = String::new();
+        let mut upper = 0;
+        for (i, chunk) in image.chunks(16).enumerate() {
+            let addr = i * 16;
+            if addr >> 16 != upper {
+                upper = addr >> 16;
+                out.push_str(&ihex_record(0x04, 0, &[(upper >> 8) as u8, upper as u8]));
+            }
+            out.push_str(&ihex_record(0x00, addr as u16, chunk));
+        }
+        out.push_str(&ihex_record(0x01, 0, &[]));
+        out
+    }
+
+    fn dump_binary(&self, big_endian: bool) -> Vec<u8> {
+        let word = Self::word_bytes();
+        let mut image = Vec::with_capacity(word * self.payload.len());
+        for value in self.payload.iter() {
+            let bits = ValueCastTo::<u64>::cast(value);
+            for i in 0..word {
+                let shift = if big_endian { word - 1 - i } else { i };
+                image.push((bits >> (8 * shift)) as u8);
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(array: &Array<u16>) -> Vec<u16> {
+        array.payload.clone()
+    }
+
+    #[test]
+    fn format_names() {
+        for format in ImageFormat::ALL {
+            assert_eq!(ImageFormat::parse(format.name()), Some(format));
+        }
+        assert_eq!(ImageFormat::parse("hex"), None);
+    }
+
+    #[test]
+    fn readmem() {
+        let mut array = Array::<u16>::new(8);
+        let text = "// header\n12_34 00ff /* a\ncomment */ x1z0\n@6 dead // tail\n";
+        array.load_readmem(text, 16).unwrap();
+        assert_eq!(words(&array), vec![0x1234, 0xff, 0x100, 0, 0, 0, 0xdead, 0]);
+
+        let mut array = Array::<u16>::new(2);
+        array.load_readmem("101\n@1 1111000011110000\n", 2).unwrap();
+        assert_eq!(words(&array), vec![5, 0xf0f0]);
+    }
+
+    #[test]
+    fn readmem_errors() {
+        let mut array = Array::<u16>::new(2);
+        let err = |array: &mut Array<u16>, text: &str| array.load_readmem(text, 16).unwrap_err().to_string();
+        assert_eq!(err(&mut array, "1\n@2 3\n"), "line 2: address 0x2 is out of range for an array of 2 words");
+        assert_eq!(err(&mut array, "12345\n"), "line 1: `12345` does not fit in 16 bits");
+        assert_eq!(err(&mut array, "@g\n"), "line 1: invalid address `@g`");
+        assert_eq!(err(&mut array, "0 1q\n"), "line 1: invalid word `1q`");
+        assert_eq!(err(&mut array, "0\n/* open\n1\n"), "line 3: unterminated block comment");
+        assert!(array.load_readmem("2", 2).is_err());
+    }
+
+    #[test]
+    fn ihex_round_trip() {
+        let mut array = Array::<u16>::new_with_init((0..40).map(|i| i * 0x101).collect());
+        let text = array.dump_ihex();
+        let mut loaded = Array::<u16>::new(40);
+        loaded.load_ihex(&text).unwrap();
+        assert_eq!(words(&loaded), words(&array));
+
+        // an extended segment address moves the base by 16 bytes a unit
+        array = Array::<u16>::new(16);
+        array.load_ihex(":020000020001FB\n:020000003412B8\n:00000001FF\n").unwrap();
+        assert_eq!(array.payload[8], 0x1234);
+    }
+
+    #[test]
+    fn ihex_errors() {
+        let mut array = Array::<u16>::new(4);
+        let err = |array: &mut Array<u16>, text: &str| array.load_ihex(text).unwrap_err().to_string();
+        assert_eq!(err(&mut array, "020000003412B8\n"), "line 1: record does not start with `:`");
+        assert_eq!(err(&mut array, ":0200\n"), "line 1: malformed record");
+        assert_eq!(err(&mut array, ":020000003412B9\n"), "line 1: checksum mismatch");
+        assert_eq!(err(&mut array, ":0200000034G2B8\n"), "line 1: invalid hex digit");
+        assert_eq!(err(&mut array, ":030000003412B8\n"), "line 1: record declares 3 data bytes but has 2");
+        assert_eq!(err(&mut array, ":00000006FA\n"), "line 1: unsupported record type 06");
+        assert_eq!(
+            err(&mut array, ":020010003412A8\n"),
+            "line 1: address 0x8 is out of range for an array of 4 words"
+        );
+        // nothing after the end of file record is read
+        array.load_ihex(":00000001FF\ngarbage\n").unwrap();
+    }
+
+    #[test]
+    fn binary() {
+        let mut array = Array::<u16>::new(3);
+        array.load_binary(&[0x12, 0x34, 0x56, 0x78], false).unwrap();
+        assert_eq!(words(&array), vec![0x3412, 0x7856, 0]);
+        array.load_binary(&[0x12, 0x34], true).unwrap();
+        assert_eq!(array.payload[0], 0x1234);
+        assert_eq!(array.dump_binary(true), vec![0x12, 0x34, 0x78, 0x56, 0, 0]);
+        assert!(matches!(
+            array.load_binary(&[0; 3], false),
+            Err(ImageError::Truncated { len: 3, word: 2 })
+        ));
+        assert!(matches!(
+            array.load_binary(&[0; 8], false),
+            Err(ImageError::TooLarge { words: 4, size: 3 })
+        ));
+    }
+}
@@ -1,8 +1,360 @@
-mod memory_interface;
+mod addr_map;
+mod async_fifo;
+mod cache;
+mod checkpoint;
+mod clock;
+mod config;
+mod convert;
+mod cpu;
+mod debugger;
+mod event_log;
+mod image;
+// modules.rs and runtime.rs are generated by Assassyn and keep its names
+// and idioms, the runtime also carries helpers this design does not use.
+#[allow(
+    non_snake_case,
+    clippy::clone_on_copy,
+    clippy::identity_op,
+    clippy::just_underscores_and_digits
+)]
 mod modules;
+mod reorder_buffer;
+mod rng;
+#[allow(
+    dead_code,
+    clippy::upper_case_acronyms,
+    clippy::clone_on_copy,
+    clippy::needless_return,
+    clippy::unnecessary_cast,
+    clippy::redundant_pattern_matching
+)]
 mod runtime;
 mod simulator;
+mod stats;
+mod sweep;
+mod watch;
+
+use memory_interface::BackendKind;
+use std::process::exit;
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: memory_simulator [--seed N] [--clock-period N] [--core-mhz F] \
+         [--backend ramulator|ramulator-static|mock] [--rob-depth N] [--out-of-order] [--every-cycle] [--cache SPEC] \
+         [--traffic conflict|spread[:COUNT]] [--dram-config PATH] [--dram-set KEY=VALUE]... [--verify-determinism] [--debug] \
+         [--checkpoint-at CYCLE PATH] [--restore PATH] [--stats-json PATH] [--mem-trace PATH] [--watch 'TARGET[ADDR] OP VALUE ACTION']... \
+         [--load ARRAY FORMAT PATH]... [--dump ARRAY FORMAT PATH]..."
+    );
+    eprintln!(
+        "       memory_simulator sweep [--set KEY=V1,V2,...]... [--workload stream|random|conflict|spread[:COUNT]] \
+         [--seed N] [--dram-config PATH] [--backend NAME] [--csv PATH]"
+    );
+    eprintln!("       memory_simulator convert --from FORMAT --to FORMAT INPUT OUTPUT");
+    eprintln!(
+        "       memory_simulator cpu [--trace PATH] [--trace-format FORMAT] [--rob-size N] [--issue-width N] \
+         [--core-ratio N] [--insts N] [--dram-config PATH] [--dram-set KEY=VALUE]... [--backend NAME] [--mem-trace PATH]"
+    );
+    eprintln!("       (trace formats: inst, loadstore, dramsim3, memtrace, champsim)");
+    eprintln!("       (image formats: readmemh, readmemb, ihex, bin, bin-be)");
+    exit(1);
+}
+
+fn load_dram_config(path: &str) -> config::DramConfig {
+    match config::DramConfig::load(path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load DRAM config {}: {}", path, e);
+            exit(1);
+        }
+    }
+}
+
+// A backend built into this binary, exiting with the ones that are if not.
+fn parse_backend(name: Option<String>) -> BackendKind {
+    let compiled: Vec<&str> = BackendKind::compiled().iter().map(BackendKind::name).collect();
+    match name.as_deref().map(BackendKind::parse) {
+        Some(Some(kind)) if kind.is_compiled() => kind,
+        Some(_) => {
+            eprintln!(
+                "Unknown or unavailable backend {}, this build has: {}",
+                name.unwrap(),
+                compiled.join(", ")
+            );
+            exit(1);
+        }
+        None => usage(),
+    }
+}
+
+fn sweep_main(mut args: impl Iterator<Item = String>) {
+    let mut opts = sweep::SweepOptions {
+        count: 1024,
+        ..Default::default()
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--set" => match args.next().as_deref().and_then(|s| s.split_once('=')) {
+                Some((key, values)) => opts
+                    .axes
+                    .push((key.to_string(), values.split(',').map(str::to_string).collect())),
+                None => usage(),
+            },
+            "--workload" => {
+                let spec = args.next().unwrap_or_else(|| usage());
+                let (name, count) = match spec.split_once(':') {
+                    Some((name, count)) => (name, count.parse().ok()),
+                    None => (spec.as_str(), Some(opts.count)),
+                };
+                match (sweep::Workload::parse(name), count) {
+                    (Some(workload), Some(count)) => {
+                        opts.workload = Some(workload);
+                        opts.count = count;
+                    }
+                    _ => usage(),
+                }
+            }
+            "--seed" => match args.next().and_then(|s| s.parse().ok()) {
+                Some(seed) => opts.seed = seed,
+                None => usage(),
+            },
+            "--dram-config" => match args.next() {
+                Some(path) => opts.base = load_dram_config(&path),
+                None => usage(),
+            },
+            "--backend" => opts.backend = Some(parse_backend(args.next())),
+            "--csv" => match args.next() {
+                Some(path) => opts.csv = Some(path),
+                None => usage(),
+            },
+            _ => usage(),
+        }
+    }
+    if let Err(e) = sweep::sweep(&opts) {
+        eprintln!("Sweep failed: {}", e);
+        exit(1);
+    }
+}
+
+fn parse_format(name: Option<String>) -> convert::TraceFormat {
+    match name.as_deref().and_then(convert::TraceFormat::parse) {
+        Some(format) => format,
+        None => usage(),
+    }
+}
+
+fn convert_main(mut args: impl Iterator<Item = String>) {
+    let mut from = None;
+    let mut to = None;
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => from = Some(parse_format(args.next())),
+            "--to" => to = Some(parse_format(args.next())),
+            _ if !arg.starts_with("--") => paths.push(arg),
+            _ => usage(),
+        }
+    }
+    let (Some(from), Some(to), [input, output]) = (from, to, paths.as_slice()) else {
+        usage()
+    };
+    match convert::convert(from, input, to, output) {
+        Ok(count) => println!(
+            "Converted {} requests from {} ({}) to {} ({})",
+            count,
+            input,
+            from.name(),
+            output,
+            to.name()
+        ),
+        Err(e) => {
+            eprintln!("Conversion failed: {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn cpu_main(mut args: impl Iterator<Item = String>) {
+    let mut opts = cpu::CpuOptions::default();
+    let mut dram_overrides = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => match args.next() {
+                Some(path) => opts.trace = path,
+                None => usage(),
+            },
+            "--trace-format" => opts.format = parse_format(args.next()),
+            "--rob-size" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(size) if size > 0 => opts.rob_size = size,
+                _ => usage(),
+            },
+            "--issue-width" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(width) if width > 0 => opts.issue_width = width,
+                _ => usage(),
+            },
+            "--core-ratio" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(ratio) if ratio > 0 => opts.core_ratio = ratio,
+                _ => usage(),
+            },
+            "--insts" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(insts) => opts.insts = Some(insts),
+                None => usage(),
+            },
+            "--dram-config" => match args.next() {
+                Some(path) => opts.dram = load_dram_config(&path),
+                None => usage(),
+            },
+            "--dram-set" => match args.next() {
+                Some(o) => dram_overrides.push(o),
+                None => usage(),
+            },
+            "--backend" => opts.backend = Some(parse_backend(args.next())),
+            "--mem-trace" => match args.next() {
+                Some(path) => opts.mem_trace = Some(path),
+                None => usage(),
+            },
+            _ => usage(),
+        }
+    }
+    if let Err(e) = opts.dram.merge(&dram_overrides) {
+        eprintln!("Invalid DRAM config override: {}", e);
+        exit(1);
+    }
+    if let Err(e) = cpu::simulate(&opts) {
+        eprintln!("Core simulation failed: {}", e);
+        exit(1);
+    }
+}
 
 fn main() {
-    simulator::simulate();
+    let mut args = std::env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("sweep") => {
+            args.next();
+            sweep_main(args);
+            return;
+        }
+        Some("convert") => {
+            args.next();
+            convert_main(args);
+            return;
+        }
+        Some("cpu") => {
+            args.next();
+            cpu_main(args);
+            return;
+        }
+        _ => {}
+    }
+    let mut opts = simulator::SimOptions::default();
+    let mut verify = false;
+    let mut dram_config = None;
+    let mut dram_overrides = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--checkpoint-at" => {
+                let cycle = args.next().and_then(|c| c.parse().ok());
+                match (cycle, args.next()) {
+                    (Some(cycle), Some(path)) => opts.checkpoint = Some((cycle, path)),
+                    _ => usage(),
+                }
+            }
+            "--restore" => match args.next() {
+                Some(path) => opts.restore = Some(path),
+                None => usage(),
+            },
+            "--seed" => match args.next().and_then(|s| s.parse().ok()) {
+                Some(seed) => opts.seed = seed,
+                None => usage(),
+            },
+            "--clock-period" => match args.next().and_then(|p| p.parse::<usize>().ok()) {
+                Some(period) if period >= 2 && period % 2 == 0 => opts.clock_period = Some(period),
+                _ => usage(),
+            },
+            "--core-mhz" => match args.next().and_then(|f| f.parse::<f64>().ok()) {
+                Some(mhz) if mhz > 0.0 => opts.core_mhz = Some(mhz),
+                _ => usage(),
+            },
+            "--rob-depth" => match args.next().and_then(|d| d.parse::<usize>().ok()) {
+                Some(depth) if depth > 0 => opts.rob_depth = Some(depth),
+                _ => usage(),
+            },
+            "--out-of-order" => opts.out_of_order = true,
+            "--every-cycle" => opts.every_cycle = true,
+            "--cache" => match args.next().map(|spec| cache::CacheConfig::parse(&spec)) {
+                Some(Ok(config)) => opts.cache = Some(config),
+                Some(Err(e)) => {
+                    eprintln!("invalid cache: {}", e);
+                    usage()
+                }
+                None => usage(),
+            },
+            "--traffic" => {
+                let spec = args.next().unwrap_or_else(|| usage());
+                let (pattern, count) = match spec.split_once(':') {
+                    Some((pattern, count)) => (pattern, count.parse().ok()),
+                    None => (spec.as_str(), Some(64)),
+                };
+                match (addr_map::TrafficPattern::parse(pattern), count) {
+                    (Some(pattern), Some(count)) => opts.traffic = Some((pattern, count)),
+                    _ => usage(),
+                }
+            }
+            "--dram-config" => match args.next() {
+                Some(path) => dram_config = Some(path),
+                None => usage(),
+            },
+            "--dram-set" => match args.next() {
+                Some(o) => dram_overrides.push(o),
+                None => usage(),
+            },
+            "--backend" => opts.backend = Some(parse_backend(args.next())),
+            "--verify-determinism" => verify = true,
+            "--debug" => opts.debug = true,
+            "--stats-json" => match args.next() {
+                Some(path) => opts.stats_json = Some(path),
+                None => usage(),
+            },
+            "--mem-trace" => match args.next() {
+                Some(path) => opts.mem_trace = Some(path),
+                None => usage(),
+            },
+            "--watch" => match args.next() {
+                Some(spec) => opts.watches.push(spec),
+                None => usage(),
+            },
+            "--load" | "--dump" => {
+                let name = args.next();
+                let format = args.next().as_deref().and_then(image::ImageFormat::parse);
+                let (Some(name), Some(format), Some(path)) = (name, format, args.next()) else {
+                    usage()
+                };
+                if arg == "--load" {
+                    opts.loads.push((name, format, path));
+                } else {
+                    opts.dumps.push((name, format, path));
+                }
+            }
+            _ => usage(),
+        }
+    }
+    if let Some(path) = &dram_config {
+        opts.dram = load_dram_config(path);
+    }
+    if let Err(e) = opts.dram.merge(&dram_overrides) {
+        eprintln!("Invalid DRAM config override: {}", e);
+        exit(1);
+    }
+    let result = if verify {
+        simulator::verify_determinism(&opts)
+    } else {
+        simulator::simulate(&opts).map(|_| true)
+    };
+    match result {
+        Ok(true) => {}
+        Ok(false) => exit(1),
+        Err(e) => {
+            eprintln!("Simulation failed: {}", e);
+            exit(1);
+        }
+    }
 }
@@ -1,47 +1,428 @@
+use super::async_fifo::AsyncFIFO;
+use super::cache::{Access, WritePolicy};
+use super::checkpoint::{self, CheckpointError, Tokens};
+use super::clock::{cyclize, Phase};
+use super::image::ImageFormat;
 use super::runtime::*;
-use super::simulator::Simulator;
-use crate::memory_interface::MemoryInterface;
-use crate::memory_interface::Request;
-use libloading::{Library, Symbol};
-use num_bigint::{BigInt, BigUint};
+use super::simulator::{Design, ModuleDef, Simulator};
+use super::stats::Stall;
+use super::watch::{WatchHit, WatchSpec};
+use memory_interface::{MemoryError, Request, RequestKind};
+use std::any::Any;
+use std::cell::RefCell;
 use std::collections::VecDeque;
-use std::ffi::CString;
-use std::ffi::{c_char, c_float, c_longlong, c_void};
-use std::sync::Arc;
-
-extern "C" fn rust_callback(req: *mut Request, ctx: *mut c_void) {
-    unsafe {
-        //println!("we successfully push data!!!!!!");
-        let req = &*req;
-        let sim: &mut Simulator = &mut *(ctx as *mut Simulator);
-        // skip the write request.
-        let cycles = (req.depart - req.arrive) as usize;
+use std::ffi::c_void;
+use std::fmt::Write;
+use std::rc::Rc;
+
+// The design's arrays, FIFOs and the values Driver hands SRAM_2a9ed, shared
+// by its modules. SRAM_2a9ed runs in the memory's clock domain, and Driver
+// reaches it through an AsyncFIFO, when that clock differs from the core's.
+pub struct DesignState {
+    pub array_2a959: Array<i32>,
+    pub array_2aa85: Array<u32>,
+    pub MemUser_57a15_rdata: FIFO<u32>,
+    pub _2a969_value: Option<bool>,
+    pub _2aa41_value: Option<i16>,
+    pub _2a991_value: Option<bool>,
+    pub _2aa91_value: Option<u32>,
+    pub Driver_SRAM_2a9ed_req: Option<AsyncFIFO<SramRequest>>,
+}
+
+// What Driver asks of SRAM_2a9ed, sent across into the memory domain.
+#[derive(Clone, Copy)]
+pub struct SramRequest {
+    pub write_enable: bool,
+    pub read_enable: bool,
+    pub addr: i16,
+    pub data: u32,
+}
+
+impl DesignState {
+    pub fn new() -> Self {
+        DesignState {
+            array_2a959: Array::new(1),       // cycle
+            array_2aa85: Array::new(512),     // sram size
+            MemUser_57a15_rdata: FIFO::new(), // read data
+            _2a969_value: None,
+            _2aa41_value: None,
+            _2a991_value: None,
+            _2aa91_value: None,
+            Driver_SRAM_2a9ed_req: None,
+        }
+    }
+
+    // What SRAM_2a9ed serves now: Driver's values in the same domain, else
+    // the oldest request that has made it across.
+    fn sram_request(&self, stamp: usize) -> Option<SramRequest> {
+        match &self.Driver_SRAM_2a9ed_req {
+            Some(fifo) => fifo.front(stamp).copied(),
+            None => Some(SramRequest {
+                write_enable: self._2a969_value?,
+                read_enable: self._2a991_value?,
+                addr: self._2aa41_value?,
+                data: self._2aa91_value?,
+            }),
+        }
+    }
+
+    pub fn print_rdata_state(&self, stamp: usize) {
+        println!("\n=== MemUser_57a15_rdata State at {} ===", cyclize(stamp));
+
+        // Print payload
+        println!("Payload Queue:");
+        if self.MemUser_57a15_rdata.payload.is_empty() {
+            println!("  [Empty]");
+        } else {
+            for (i, value) in self.MemUser_57a15_rdata.payload.iter().enumerate() {
+                println!("  [{:2}]: {:?}", i, value);
+            }
+        }
+
+        // Print push queue (XEQ<FIFOPush>)
+        println!("\nPush Queue (XEQ):");
+        if self.MemUser_57a15_rdata.push.is_empty() {
+            println!("  [Empty]");
+        } else {
+            // Print each push event's cycle and pusher
+            for (cycle, _) in self.MemUser_57a15_rdata.push.q.iter() {
+                println!("  Cycle: {}", cyclize(*cycle));
+            }
+        }
+
+        // Print pop queue (XEQ<FIFOPop>)
+        println!("\nPop Queue (XEQ):");
+        if self.MemUser_57a15_rdata.pop.is_empty() {
+            println!("  [Empty]");
+        } else {
+            // Print each pop event's cycle and pusher
+            for (cycle, _) in self.MemUser_57a15_rdata.pop.q.iter() {
+                println!("  Cycle: {}", cyclize(*cycle));
+            }
+        }
+        println!("=====================================\n");
+    }
+}
+
+impl Design for DesignState {
+    fn tick_registers(&mut self, stamp: usize) {
+        self.array_2a959.tick(stamp);
+
+        //we tick that one into the payload when the write is not empty.
+        if !self.array_2aa85.write.is_empty() {
+            self.array_2aa85.tick(stamp);
+            println!("now write finish his job.")
+        }
+        self.MemUser_57a15_rdata.tick(stamp);
+    }
+
+    fn next_event(&self) -> Option<usize> {
+        [
+            self.array_2a959.write.next_cycle(),
+            self.array_2aa85.write.next_cycle(),
+            self.MemUser_57a15_rdata.push.next_cycle(),
+            self.MemUser_57a15_rdata.pop.next_cycle(),
+            self.Driver_SRAM_2a9ed_req.as_ref().and_then(|fifo| fifo.next_visible()),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    fn array_word(&self, name: &str, addr: usize) -> Option<u64> {
+        match name {
+            "array_2a959" => self.array_2a959.payload.get(addr).map(ValueCastTo::<u64>::cast),
+            "array_2aa85" => self.array_2aa85.payload.get(addr).map(ValueCastTo::<u64>::cast),
+            _ => None,
+        }
+    }
+
+    fn add_watch(&mut self, spec: WatchSpec) -> Result<(), String> {
+        match spec.target.as_str() {
+            "array_2a959" => self.array_2a959.watch(spec),
+            "array_2aa85" => self.array_2aa85.watch(spec),
+            "MemUser_57a15_rdata" => self.MemUser_57a15_rdata.watch(spec),
+            _ => Err(format!("no array or FIFO named {}", spec.target)),
+        }
+    }
+
+    fn remove_watch(&mut self, label: &str) -> bool {
+        self.array_2a959.unwatch(label)
+            | self.array_2aa85.unwatch(label)
+            | self.MemUser_57a15_rdata.unwatch(label)
+    }
+
+    fn watch_labels(&self) -> Vec<String> {
+        let mut labels = self.array_2a959.watch_labels();
+        labels.extend(self.array_2aa85.watch_labels());
+        labels.extend(self.MemUser_57a15_rdata.watch_labels());
+        labels
+    }
+
+    fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        let mut hits = std::mem::take(&mut self.array_2a959.hits);
+        hits.append(&mut self.array_2aa85.hits);
+        hits.append(&mut self.MemUser_57a15_rdata.hits);
+        hits
+    }
+
+    fn print_fifo(&self, name: &str, stamp: usize) -> bool {
+        match name {
+            "MemUser_57a15_rdata" => self.print_rdata_state(stamp),
+            _ => return false,
+        }
+        true
+    }
+
+    fn load_array(&mut self, name: &str, path: &str, format: ImageFormat) -> Result<(), String> {
+        match name {
+            "array_2a959" => self.array_2a959.load(path, format),
+            "array_2aa85" => self.array_2aa85.load(path, format),
+            _ => return Err(format!("no array named {}", name)),
+        }
+        .map_err(|e| e.to_string())
+    }
+
+    fn dump_array(&self, name: &str, path: &str, format: ImageFormat) -> Result<(), String> {
+        match name {
+            "array_2a959" => self.array_2a959.dump(path, format),
+            "array_2aa85" => self.array_2aa85.dump(path, format),
+            _ => return Err(format!("no array named {}", name)),
+        }
+        .map_err(|e| e.to_string())
+    }
+
+    fn save(&self, out: &mut String) {
+        checkpoint::save_array(out, "array_2a959", &self.array_2a959);
+        checkpoint::save_array(out, "array_2aa85", &self.array_2aa85);
+        checkpoint::save_fifo(out, "MemUser_57a15_rdata", &self.MemUser_57a15_rdata);
+        // only there when SRAM_2a9ed has a clock of its own
+        if let Some(fifo) = &self.Driver_SRAM_2a9ed_req {
+            write!(out, "async_fifo Driver_SRAM_2a9ed_req {}", fifo.occupancy()).unwrap();
+            for (visible, req) in fifo.payload.iter() {
+                write!(
+                    out,
+                    " {} {} {} {} {}",
+                    visible, req.write_enable, req.read_enable, req.addr, req.data
+                )
+                .unwrap();
+            }
+            write!(out, " {}", fifo.freed.len()).unwrap();
+            for stamp in fifo.freed.iter() {
+                write!(out, " {}", stamp).unwrap();
+            }
+            out.push('\n');
+        }
+    }
+
+    fn restore(&mut self, t: &mut Tokens) -> Result<(), CheckpointError> {
+        checkpoint::restore_array(t, "array_2a959", &mut self.array_2a959)?;
+        checkpoint::restore_array(t, "array_2aa85", &mut self.array_2aa85)?;
+        checkpoint::restore_fifo(t, "MemUser_57a15_rdata", &mut self.MemUser_57a15_rdata)?;
+        if let Some(fifo) = &mut self.Driver_SRAM_2a9ed_req {
+            t.expect("async_fifo")?;
+            t.expect("Driver_SRAM_2a9ed_req")?;
+            fifo.payload.clear();
+            for _ in 0..t.num::<usize>("request count")? {
+                let visible = t.num("visible stamp")?;
+                let req = SramRequest {
+                    write_enable: t.num("write enable")?,
+                    read_enable: t.num("read enable")?,
+                    addr: t.num("request address")?,
+                    data: t.num("request data")?,
+                };
+                fifo.payload.push_back((visible, req));
+            }
+            fifo.freed.clear();
+            for _ in 0..t.num::<usize>("freed count")? {
+                fifo.freed.push_back(t.num("freed stamp")?);
+            }
+        }
+        Ok(())
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Hand the data of a finished read to the reorder buffer and push whatever
+// it releases into the consumer FIFO, at most one entry per cycle.
+fn complete_read(sim: &mut Simulator, d: &mut DesignState, addr: i64, tag: usize, mut arrive: usize) {
+    let data = d.array_2aa85.payload[addr as usize].clone();
+    sim.rob.complete(tag, data);
+    for data in sim.rob.release() {
+        while d.MemUser_57a15_rdata.push.q.contains_key(&arrive) {
+            arrive += sim.clock.period;
+        }
+        d.MemUser_57a15_rdata
+            .push
+            .push(FIFOPush::new(arrive, data, "SRAM_2a9ed"));
+    }
+}
+
+// Who waits on a read sent to memory.
+#[derive(Clone, Copy)]
+pub enum Reader {
+    Design(usize), // a design read, by reorder buffer tag
+    Line,          // a cache line fetch
+    Traffic,       // generated traffic, its data is dropped
+}
+
+// A read memory finished, left by the callback for finish_reads.
+pub struct ReadDone {
+    addr: i64,
+    cycles: usize,
+    reader: Reader,
+}
+
+// Every read carries its own ctx, freed by the callback.
+struct ReadCtx {
+    done: Rc<RefCell<VecDeque<ReadDone>>>,
+    reader: Reader,
+}
+
+// The backend is mid-tick and the simulator busy ticking it, so the callback
+// only queues the read.
+fn rust_callback(req: &Request, ctx: *mut c_void) {
+    // writes are sent without a ctx and never complete
+    if ctx.is_null() {
+        panic!("Request for {} completed without a ctx!", req.addr);
+    }
+    let ctx = unsafe { Box::from_raw(ctx as *mut ReadCtx) };
+    ctx.done.borrow_mut().push_back(ReadDone {
+        addr: req.addr,
+        cycles: (req.depart - req.arrive) as usize,
+        reader: ctx.reader,
+    });
+}
+
+// Hand the reads the last memory tick finished to whoever waits on them, in
+// the order they finished.
+pub fn finish_reads(sim: &mut Simulator) {
+    let done: Vec<ReadDone> = sim.mem_done.borrow_mut().drain(..).collect();
+    if done.is_empty() {
+        return;
+    }
+    // memory is ticked between module evaluations, nothing holds the state
+    let design = Rc::clone(&sim.design);
+    let mut design = design.borrow_mut();
+    let d = design
+        .as_any()
+        .downcast_mut::<DesignState>()
+        .expect("Read completed for another design!");
+    for read in done {
         let stamp = sim.stamp;
-        //println!("Request arrived at {}", stamp + 50 + 100 * cycles);
-        println!("req address is: {}, the data is: {}, and the latency is: {}", req.addr, sim.array_2aa85.payload[req.addr as usize], cyclize(stamp + 50 + 100 * cycles));
-        sim.MemUser_57a15_rdata.push.push(FIFOPush::new(
-            stamp + 50 + 100 * cycles,
-            sim.array_2aa85.payload[req.addr as usize].clone(),
-            "SRAM_2a9ed",
-        ));
-        //sim.print_rdata_state();
+        sim.log.record(stamp, format!("mem_done {} {}", read.addr, read.cycles));
+        sim.record_bank(read.addr, Some(read.cycles));
+        sim.mem_reads -= 1;
+        let arrive = sim.clock.next_cycle(stamp) + sim.mem_clock.cycles(read.cycles);
+        match read.reader {
+            Reader::Traffic => sim.traffic_outstanding -= 1,
+            Reader::Design(tag) => {
+                println!("req address is: {}, the data is: {}, and the latency is: {}", read.addr, d.array_2aa85.payload[read.addr as usize], cyclize(arrive));
+                complete_read(sim, d, read.addr, tag, arrive);
+            }
+            Reader::Line => {
+                let cache = sim.cache.as_mut().expect("Cache line filled without a cache!");
+                if !cache.is_pending(read.addr) {
+                    panic!("Cache line {} filled but never fetched!", read.addr);
+                }
+                for (addr, tag) in cache.fill(read.addr) {
+                    complete_read(sim, d, addr, tag, arrive);
+                }
+            }
+        }
     }
 }
 
+// A request memory accepted and the memory cycle it went in on. The backend
+// cannot be snapshotted, a checkpoint replays these into a fresh one.
+#[derive(Clone, Copy)]
+pub struct Sent {
+    pub cycle: u64,
+    pub addr: i64,
+    pub kind: RequestKind,
+    pub reader: Option<Reader>,
+}
+
+// Send a request to memory, false if it has no room for it. Any other
+// failure leaves the backend unusable. Reads name their reader, writes
+// have none.
+pub fn send(sim: &mut Simulator, addr: i64, kind: RequestKind, reader: Option<Reader>) -> Result<bool, MemoryError> {
+    let mem_interface = Rc::clone(&sim.mem_interface);
+    let ctx = match reader {
+        Some(reader) => {
+            let done = Rc::clone(&sim.mem_done);
+            Box::into_raw(Box::new(ReadCtx { done, reader })) as *mut c_void
+        }
+        None => std::ptr::null_mut(),
+    };
+    let sent = unsafe { mem_interface.send_request(addr, kind, rust_callback, ctx) };
+    if sent.is_err() && !ctx.is_null() {
+        drop(unsafe { Box::from_raw(ctx as *mut ReadCtx) });
+    }
+    match sent {
+        Ok(()) => {
+            sim.mem_reads += reader.is_some() as usize;
+            sim.mem_sent.push(Sent {
+                cycle: mem_interface.cycle(),
+                addr,
+                kind,
+                reader,
+            });
+            Ok(true)
+        }
+        Err(MemoryError::QueueFull) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// Send the cache's line fetches and write-backs, stopping at the first one
+// memory refuses. Not called from the callback, the backend is mid-tick there.
+pub fn issue_misses(sim: &mut Simulator) -> Result<(), MemoryError> {
+    let Some(cache) = sim.cache.as_ref() else {
+        return Ok(());
+    };
+    for line in cache.unsent() {
+        let success = send(sim, line, RequestKind::Read, Some(Reader::Line))?;
+        sim.log.record(sim.stamp, format!("cache_fetch {} {}", line, success));
+        if !success {
+            return Ok(());
+        }
+        sim.cache.as_mut().unwrap().mark_sent(line);
+    }
+    while let Some(line) = sim.cache.as_ref().and_then(|c| c.next_writeback()) {
+        let success = send(sim, line, RequestKind::Write, None)?;
+        sim.log.record(sim.stamp, format!("cache_writeback {} {}", line, success));
+        if !success {
+            return Ok(());
+        }
+        sim.record_bank(line, None);
+        sim.cache.as_mut().unwrap().writeback_sent();
+    }
+    Ok(())
+}
+
 // Elaborating module MemUser_57a15
-pub fn MemUser_57a15(sim: &mut Simulator) -> bool {
+pub fn MemUser_57a15(sim: &mut Simulator, d: &mut DesignState) -> Result<bool, MemoryError> {
     // but we do not know whether the rdata has value, so we need to check it.
     let _27689 = {
-        let stamp = sim.stamp - sim.stamp % 100 + 50; // 150
-        sim.MemUser_57a15_rdata
-            .pop
-            .push(FIFOPop::new(stamp, "MemUser_57a15"));
-
         // Add safe unwrapping with default value or early return
-        match sim.MemUser_57a15_rdata.payload.front() {
+        let value = match d.MemUser_57a15_rdata.payload.front() {
             Some(value) => value.clone(),
-            None => return false, // No data available yet
-        }
+            None => {
+                // No data available yet, and nothing to pop: a stray pop
+                // would take data pushed at a memory edge before our commit
+                sim.stall(Stall::FifoEmpty);
+                return Ok(false);
+            }
+        };
+        let stamp = sim.clock.next_commit(sim.stamp); // 150
+        d.MemUser_57a15_rdata
+            .pop
+            .push(FIFOPop::new(stamp, "MemUser_57a15"));
+        value
     };
 
     let _2a915 = ValueCastTo::<i32>::cast(&_27689);
@@ -53,17 +434,17 @@ pub fn MemUser_57a15(sim: &mut Simulator) -> bool {
         cyclize(sim.stamp)
     );
     println!("{} + {} = {}", _2a915, 128i32, _2a921);
-    if _2a915 == 15 || _2a915 == 13 {
-        // 15 + 128 appears twice, we need to know why.
-        sim.print_rdata_state();
-    }
 
-    true
+    Ok(true)
 }
 // Elaborating module Driver
-pub fn Driver(sim: &mut Simulator) -> bool {
+pub fn Driver(sim: &mut Simulator, d: &mut DesignState) -> Result<bool, MemoryError> {
+    if d.Driver_SRAM_2a9ed_req.as_ref().is_some_and(|fifo| fifo.is_full(sim.stamp)) {
+        sim.stall(Stall::MemBackpressure);
+        return Ok(false);
+    }
     // read the cycle
-    let _2a961 = { sim.array_2a959.payload[0u8 as usize].clone() };
+    let _2a961 = { d.array_2a959.payload[0u8 as usize].clone() };
     let _2a969 = {
         {
             let a = ValueCastTo::<u64>::cast(&_2a961);
@@ -72,9 +453,9 @@ pub fn Driver(sim: &mut Simulator) -> bool {
             ValueCastTo::<bool>::cast(&res)
         }
     };
-    sim._2a969_value = Some(_2a969.clone());
+    d._2a969_value = Some(_2a969.clone());
     let _2a991 = { !_2a969 };
-    sim._2a991_value = Some(_2a991.clone());
+    d._2a991_value = Some(_2a991.clone());
     let _2a9b5 = { ValueCastTo::<i32>::cast(&_2a961) + ValueCastTo::<i32>::cast(&1i32) };
     let _2a9cd = {
         {
@@ -100,11 +481,11 @@ pub fn Driver(sim: &mut Simulator) -> bool {
         }
     };
     let _2aa41 = { ValueCastTo::<i16>::cast(&_2aa21) };
-    sim._2aa41_value = Some(_2aa41.clone());
+    d._2aa41_value = Some(_2aa41.clone());
     {
-        let stamp = sim.stamp - sim.stamp % 100 + 50;
+        let stamp = sim.clock.next_commit(sim.stamp);
         // push to the cycle.
-        sim.array_2a959.write.push(ArrayWrite::new(
+        d.array_2a959.write.push(ArrayWrite::new(
             stamp,
             false as usize,
             _2a9b5.clone(),
@@ -113,18 +494,30 @@ pub fn Driver(sim: &mut Simulator) -> bool {
     };
     //read enable
     let _2aa91 = { ValueCastTo::<u32>::cast(&_2a961) };
-    sim._2aa91_value = Some(_2aa91.clone());
+    d._2aa91_value = Some(_2aa91.clone());
+    let request = SramRequest {
+        write_enable: _2a969,
+        read_enable: _2a991,
+        addr: _2aa41,
+        data: _2aa91,
+    };
+    // across clock domains SRAM_2a9ed is woken once the request is through
+    if let Some(fifo) = d.Driver_SRAM_2a9ed_req.as_mut() {
+        if let Some(stamp) = fifo.push(sim.stamp, request) {
+            sim.schedule("SRAM_2a9ed", stamp);
+        }
+    }
     if _2a991 {
         {
-            let stamp = sim.stamp - sim.stamp % 100 + 100;
-            sim.MemUser_57a15_event.push_back(stamp)
+            let stamp = sim.clock.next_cycle(sim.stamp);
+            sim.schedule("MemUser_57a15", stamp)
         };
     }
 
-    true
+    Ok(true)
 }
 // Elaborating module SRAM_2a9ed
-pub fn SRAM_2a9ed(sim: &mut Simulator) -> bool {
+pub fn SRAM_2a9ed(sim: &mut Simulator, d: &mut DesignState) -> Result<bool, MemoryError> {
     //println!("go inside the SRAM_2a9ed module");
     //that's the write
     // if if let Some(x) = &sim._2a969_value {
@@ -165,71 +558,176 @@ pub fn SRAM_2a9ed(sim: &mut Simulator) -> bool {
     //     ();
     //   }
 
-    // write.
-    if let Some(write_enable) = sim._2a969_value {
-        //println!("write_enable: {}", write_enable);
-        if write_enable {
-            let addr = match sim._2aa41_value {
-                Some(addr) => addr as i64,
-                None => return false,
-            };
-
-            let data = match sim._2aa91_value {
-                Some(data) => data,
-                None => return false,
-            };
-
-            unsafe {
-                let mem_interface = Arc::clone(&sim.mem_interface);
-                let success = mem_interface.as_ref().send_request(
-                    addr,
-                    true, // is_write = true
-                    rust_callback,
-                    sim as *mut Simulator as *mut c_void,
-                );
+    let Some(req) = d.sram_request(sim.stamp) else {
+        sim.stall(Stall::FifoEmpty);
+        return Ok(false);
+    };
+    let addr = req.addr as i64;
 
+    // write.
+    if req.write_enable {
+        let accepted = match sim.cache.as_mut() {
+            // a write-back cache absorbs the write, memory sees it on eviction
+            Some(cache) if cache.config.write == WritePolicy::WriteBack => {
+                let access = cache.write(addr);
+                sim.log.record(sim.stamp, format!("cache_write {} {:?}", addr, access));
+                access != Access::Blocked
+            }
+            _ => {
+                let success = send(sim, addr, RequestKind::Write, None)?;
+                sim.log.record(sim.stamp, format!("mem_write {} {}", addr, success));
                 if success {
-                    let stamp = sim.stamp - sim.stamp % 100 + 50;
-                    // write to the array
-                    sim.array_2aa85.write.push(ArrayWrite::new(
-                        stamp,
-                        addr as usize,
-                        data,
-                        "SRAM_2a9ed",
-                    ));
-                    println!("Requesting write to address: {}, data: {}", addr, data);
-                } else {
-                    sim.stamp = sim.stamp - sim.stamp % 100 + 50;
-                    return false;
+                    sim.record_bank(addr, None);
+                    if let Some(cache) = sim.cache.as_mut() {
+                        cache.write(addr);
+                    }
                 }
-            }
+                success
+            },
+        };
+
+        if accepted {
+            let stamp = sim.clock.next_commit(sim.stamp);
+            // write to the array
+            d.array_2aa85.write.push(ArrayWrite::new(
+                stamp,
+                addr as usize,
+                req.data,
+                "SRAM_2a9ed",
+            ));
+            println!("Requesting write to address: {}, data: {}", addr, req.data);
+            issue_misses(sim)?;
+        } else {
+            sim.stall(Stall::MemBackpressure);
+            return Ok(false);
         }
     }
 
     // Read operation
-    if let Some(read_enable) = sim._2a991_value {
-        //println!("read_enable: {}", read_enable);
-        if read_enable {
-            let addr = match sim._2aa41_value {
-                Some(addr) => addr as i64,
-                None => return false,
-            };
-
-            unsafe {
-                let mem_interface = Arc::clone(&sim.mem_interface);
-                println!("Requesting read from address: {}", addr);
-                let success = mem_interface.as_ref().send_request(
-                    addr,
-                    false, // is_write = false
-                    rust_callback,
-                    sim as *mut Simulator as *mut c_void,
-                );
-                if !success {
-                    return false;
-                }
+    if req.read_enable {
+        let Some(tag) = sim.rob.allocate() else {
+            sim.stall(Stall::MemBackpressure);
+            return Ok(false);
+        };
+
+        if let Some(cache) = sim.cache.as_mut() {
+            let access = cache.read(addr, tag);
+            let hit_latency = cache.config.hit_latency;
+            sim.log.record(sim.stamp, format!("cache_read {} {:?}", addr, access));
+            if access == Access::Blocked {
+                sim.rob.cancel(tag);
+                sim.stall(Stall::MemBackpressure);
+                return Ok(false);
+            }
+            if access == Access::Hit {
+                let arrive = sim.clock.next_cycle(sim.stamp) + sim.clock.cycles(hit_latency);
+                complete_read(sim, d, addr, tag, arrive);
+            }
+            issue_misses(sim)?;
+            if let Some(fifo) = d.Driver_SRAM_2a9ed_req.as_mut() {
+                fifo.pop(sim.stamp);
             }
+            return Ok(true);
         }
+
+        println!("Requesting read from address: {}", addr);
+        let success = send(sim, addr, RequestKind::Read, Some(Reader::Design(tag)))?;
+        sim.log.record(sim.stamp, format!("mem_read {} {}", addr, success));
+        if !success {
+            sim.rob.cancel(tag);
+            sim.stall(Stall::MemBackpressure);
+            return Ok(false);
+        }
+    }
+
+    if let Some(fifo) = d.Driver_SRAM_2a9ed_req.as_mut() {
+        fifo.pop(sim.stamp);
+    }
+    Ok(true)
+}
+
+// Elaborating module TrafficGen
+// Issues the generated reads, one per cycle. Their data is dropped, they
+// only load the banks.
+pub fn TrafficGen(sim: &mut Simulator, _d: &mut DesignState) -> Result<bool, MemoryError> {
+    let Some(&addr) = sim.traffic.front() else {
+        return Ok(true);
+    };
+    let success = send(sim, addr, RequestKind::Read, Some(Reader::Traffic))?;
+    sim.log.record(sim.stamp, format!("traffic_read {} {}", addr, success));
+    if !success {
+        sim.stall(Stall::MemBackpressure);
+        return Ok(false);
+    }
+    sim.traffic.pop_front();
+    sim.traffic_outstanding += 1;
+    Ok(true)
+}
+
+fn Driver_reset(d: &mut DesignState) {
+    d._2a969_value = None;
+    d._2aa41_value = None;
+    d._2a991_value = None;
+    d._2aa91_value = None;
+}
+
+fn no_reset(_d: &mut DesignState) {}
+
+pub fn elaborate(sim: &mut Simulator) {
+    // SRAM_2a9ed stays downstream of Driver unless the memory runs on its
+    // own clock, then it gets a domain and Driver's requests go through a FIFO
+    let crossing = sim.mem_clock.period != sim.clock.period;
+    let mut design = DesignState::new();
+    if crossing {
+        sim.add_domain("mem", sim.mem_clock);
+        design.Driver_SRAM_2a9ed_req = Some(AsyncFIFO::new(4, sim.clock, sim.mem_clock));
     }
+    let state = Rc::new(RefCell::new(design));
+    sim.design = state.clone();
+    // rdata is registered, so MemUser has no upstream: Driver schedules it
+    // and it retries each cycle until the read lands
+    sim.register(Rc::new(ModuleDef {
+        name: "MemUser_57a15",
+        evaluate: MemUser_57a15,
+        reset: no_reset,
+        downstreams: &[],
+        domain: "core",
+        state: state.clone(),
+    }));
+    sim.register(Rc::new(ModuleDef {
+        name: "Driver",
+        evaluate: Driver,
+        reset: Driver_reset,
+        downstreams: if crossing { &[] } else { &["SRAM_2a9ed"] },
+        domain: "core",
+        state: state.clone(),
+    }));
+    sim.register(Rc::new(ModuleDef {
+        name: "SRAM_2a9ed",
+        evaluate: SRAM_2a9ed,
+        reset: no_reset,
+        downstreams: &[],
+        domain: if crossing { "mem" } else { "core" },
+        state: state.clone(),
+    }));
 
-    true
+    for i in 1..=200 {
+        let stamp = sim.clock.at(i, Phase::Evaluate);
+        sim.schedule("Driver", stamp); // cycles 1 to 200
+    }
+
+    if !sim.traffic.is_empty() {
+        sim.register(Rc::new(ModuleDef {
+            name: "TrafficGen",
+            evaluate: TrafficGen,
+            reset: no_reset,
+            downstreams: &[],
+            domain: "core",
+            state,
+        }));
+        for i in 1..=sim.traffic.len() {
+            let stamp = sim.clock.at(i, Phase::Evaluate);
+            sim.schedule("TrafficGen", stamp);
+        }
+    }
 }
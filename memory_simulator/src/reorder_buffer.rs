@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+// Tags reads at issue and holds completions that arrive ahead of older reads,
+// so data leaves in program order. With `in_order` off completions pass
+// straight through and only the number in flight is limited.
+pub struct ReorderBuffer<T: Sized> {
+    pub depth: usize,
+    pub in_order: bool,
+    pub head: usize, // oldest tag not yet released
+    pub next_tag: usize,
+    pub outstanding: usize,
+    pub done: BTreeMap<usize, T>,
+}
+
+impl<T: Sized> ReorderBuffer<T> {
+    pub fn new(depth: usize, in_order: bool) -> Self {
+        ReorderBuffer {
+            depth,
+            in_order,
+            head: 0,
+            next_tag: 0,
+            outstanding: 0,
+            done: BTreeMap::new(),
+        }
+    }
+
+    pub fn occupancy(&self) -> usize {
+        if self.in_order {
+            self.next_tag - self.head
+        } else {
+            self.outstanding
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.occupancy() >= self.depth
+    }
+
+    pub fn allocate(&mut self) -> Option<usize> {
+        if self.is_full() {
+            return None;
+        }
+        let tag = self.next_tag;
+        self.next_tag += 1;
+        self.outstanding += 1;
+        Some(tag)
+    }
+
+    // Hand back the tag just allocated, for a read that was refused.
+    pub fn cancel(&mut self, tag: usize) {
+        if tag + 1 != self.next_tag {
+            panic!("Reorder buffer tag {} is not the last allocated!", tag);
+        }
+        self.next_tag -= 1;
+        self.outstanding -= 1;
+    }
+
+    pub fn complete(&mut self, tag: usize, data: T) {
+        if tag < self.head || tag >= self.next_tag || self.done.contains_key(&tag) {
+            panic!("Reorder buffer tag {} is not in flight!", tag);
+        }
+        self.outstanding -= 1;
+        self.done.insert(tag, data);
+    }
+
+    // Data that may leave now, oldest first.
+    pub fn release(&mut self) -> Vec<T> {
+        let mut out = Vec::new();
+        if self.in_order {
+            while let Some(data) = self.done.remove(&self.head) {
+                out.push(data);
+                self.head += 1;
+            }
+        } else {
+            while let Some((_, data)) = self.done.pop_first() {
+                out.push(data);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reorder_buffer_in_order() {
+        let mut rob = ReorderBuffer::new(4, true);
+        let tags: Vec<usize> = (0..3).map(|_| rob.allocate().unwrap()).collect();
+        assert_eq!(tags, vec![0, 1, 2]);
+        rob.complete(2, 'c');
+        rob.complete(1, 'b');
+        // held until the oldest returns
+        assert!(rob.release().is_empty());
+        rob.complete(0, 'a');
+        assert_eq!(rob.release(), vec!['a', 'b', 'c']);
+        assert_eq!(rob.occupancy(), 0);
+        assert_eq!(rob.allocate(), Some(3));
+    }
+
+    #[test]
+    fn reorder_buffer_full() {
+        let mut rob = ReorderBuffer::new(2, true);
+        let first = rob.allocate().unwrap();
+        let second = rob.allocate().unwrap();
+        assert!(rob.is_full());
+        assert_eq!(rob.allocate(), None);
+        // a completion behind the head still holds its slot
+        rob.complete(second, 2);
+        assert!(rob.release().is_empty());
+        assert_eq!(rob.allocate(), None);
+        rob.complete(first, 1);
+        assert_eq!(rob.release(), vec![1, 2]);
+        assert!(!rob.is_full());
+        // a refused read gives its tag back
+        let tag = rob.allocate().unwrap();
+        rob.cancel(tag);
+        assert_eq!(rob.allocate(), Some(tag));
+    }
+
+    #[test]
+    fn reorder_buffer_out_of_order() {
+        let mut rob = ReorderBuffer::new(2, false);
+        let first = rob.allocate().unwrap();
+        let second = rob.allocate().unwrap();
+        assert_eq!(rob.allocate(), None);
+        // completions pass straight through and free their slot
+        rob.complete(second, 2);
+        assert_eq!(rob.release(), vec![2]);
+        let third = rob.allocate().unwrap();
+        assert_eq!(rob.allocate(), None);
+        rob.complete(third, 3);
+        rob.complete(first, 1);
+        assert_eq!(rob.release(), vec![1, 3]);
+        assert_eq!(rob.occupancy(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "not in flight")]
+    fn reorder_buffer_completes_once() {
+        let mut rob = ReorderBuffer::new(2, true);
+        let tag = rob.allocate().unwrap();
+        rob.complete(tag, 0);
+        rob.complete(tag, 0);
+    }
+}
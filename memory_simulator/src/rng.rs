@@ -0,0 +1,34 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use std::cell::RefCell;
+
+// ChaCha12 is what StdRng wraps, used directly for its position in the
+// stream, which a checkpoint saves.
+thread_local! {
+    static RNG: RefCell<ChaCha12Rng> = RefCell::new(ChaCha12Rng::seed_from_u64(0));
+}
+
+// All randomness in a run must come from here so that a seed reproduces it.
+pub fn seed_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = ChaCha12Rng::seed_from_u64(seed));
+}
+
+pub fn with_rng<R>(f: impl FnOnce(&mut ChaCha12Rng) -> R) -> R {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+// The key and the word position in its stream.
+pub fn rng_state() -> ([u8; 32], u128) {
+    RNG.with(|rng| {
+        let rng = rng.borrow();
+        (rng.get_seed(), rng.get_word_pos())
+    })
+}
+
+pub fn restore_rng(seed: [u8; 32], word_pos: u128) {
+    RNG.with(|rng| {
+        let mut restored = ChaCha12Rng::from_seed(seed);
+        restored.set_word_pos(word_pos);
+        *rng.borrow_mut() = restored;
+    })
+}
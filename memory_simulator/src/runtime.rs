@@ -1,8 +1,8 @@
+use super::clock::cyclize;
+use super::watch::{Watch, WatchHit};
 use num_bigint::{BigInt, BigUint, ToBigInt, ToBigUint};
-use num_traits::Num;
 use std::collections::BTreeMap;
 use std::collections::VecDeque;
-use std::fs::read_to_string;
 
 pub trait Cycled {
     fn cycle(&self) -> usize;
@@ -25,11 +25,19 @@ impl<T: Sized + Default + Clone> ArrayWrite<T> {
             pusher,
         }
     }
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+    pub fn data(&self) -> &T {
+        &self.data
+    }
 }
 
 pub struct Array<T: Sized + Default + Clone> {
     pub payload: Vec<T>,
     pub write: XEQ<ArrayWrite<T>>,
+    pub watches: Vec<Watch<T>>,
+    pub hits: Vec<WatchHit>,
 }
 
 impl<T: Sized + Default + Clone> Array<T> {
@@ -37,16 +45,24 @@ impl<T: Sized + Default + Clone> Array<T> {
         Array {
             payload: vec![T::default(); n],
             write: XEQ::new(),
+            watches: Vec::new(),
+            hits: Vec::new(),
         }
     }
     pub fn new_with_init(payload: Vec<T>) -> Self {
         Array {
             payload,
             write: XEQ::new(),
+            watches: Vec::new(),
+            hits: Vec::new(),
         }
     }
     pub fn tick(&mut self, cycle: usize) {
         if let Some(event) = self.write.pop(cycle) {
+            for watch in self.watches.iter() {
+                let hit = watch.check(Some(event.addr), &event.data, event.cycle, event.pusher);
+                self.hits.extend(hit);
+            }
             self.payload[event.addr] = event.data;
         }
     }
@@ -66,6 +82,9 @@ impl<T: Sized> FIFOPush<T> {
             pusher,
         }
     }
+    pub fn data(&self) -> &T {
+        &self.data
+    }
 }
 
 pub struct FIFOPop {
@@ -83,6 +102,8 @@ pub struct FIFO<T: Sized> {
     pub payload: VecDeque<T>,
     pub push: XEQ<FIFOPush<T>>,
     pub pop: XEQ<FIFOPop>,
+    pub watches: Vec<Watch<T>>,
+    pub hits: Vec<WatchHit>,
 }
 
 impl<T: Sized> FIFO<T> {
@@ -91,6 +112,8 @@ impl<T: Sized> FIFO<T> {
             payload: VecDeque::new(),
             push: XEQ::new(),
             pop: XEQ::new(),
+            watches: Vec::new(),
+            hits: Vec::new(),
         }
     }
 
@@ -112,6 +135,10 @@ impl<T: Sized> FIFO<T> {
             }
         }
         if let Some(event) = self.push.pop(cycle) {
+            for watch in self.watches.iter() {
+                let hit = watch.check(None, &event.data, event.cycle, event.pusher);
+                self.hits.extend(hit);
+            }
             self.payload.push_back(event.data);
         }
     }
@@ -170,11 +197,15 @@ impl<T: Sized + Cycled> XEQ<T> {
         }
     }
 
+    pub fn next_cycle(&self) -> Option<usize> {
+        self.q.first_key_value().map(|(cycle, _)| *cycle)
+    }
+
     pub fn pop(&mut self, current: usize) -> Option<T> {
         if self
             .q
             .first_key_value()
-            .map_or(false, |(cycle, _)| *cycle <= current)
+            .is_some_and(|(cycle, _)| *cycle <= current)
         {
             self.q.pop_first().map(|(_, event)| event)
         } else {
@@ -183,35 +214,6 @@ impl<T: Sized + Cycled> XEQ<T> {
     }
 }
 
-pub fn cyclize(stamp: usize) -> String {
-    format!("Cycle @{}.{:02}", stamp / 100, stamp % 100)
-}
-
-pub fn load_hex_file<T: Num>(array: &mut Vec<T>, init_file: &str) {
-    let mut idx = 0;
-    for line in read_to_string(init_file)
-        .expect("can not open hex file")
-        .lines()
-    {
-        let line = if let Some(to_strip) = line.find("//") {
-            line[..to_strip].trim()
-        } else {
-            line.trim()
-        };
-        if line.len() == 0 {
-            continue;
-        }
-        let line = line.replace("_", "");
-        if line.starts_with("@") {
-            let addr = usize::from_str_radix(&line[1..], 16).unwrap();
-            idx = addr;
-            continue;
-        }
-        array[idx] = T::from_str_radix(line.as_str(), 16).ok().unwrap();
-        idx += 1;
-    }
-}
-
 pub trait ValueCastTo<T> {
     fn cast(&self) -> T;
 }
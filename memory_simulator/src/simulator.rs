@@ -1,230 +1,919 @@
-use super::runtime::*;
-use crate::memory_interface::MemoryInterface;
-use crate::memory_interface::Request;
-use libloading::Library;
-use num_bigint::{BigInt, BigUint};
-use rand::seq::SliceRandom;
+use super::addr_map::{AddrMapper, TrafficPattern};
+use super::cache::{Cache, CacheConfig};
+use super::checkpoint::{CheckpointError, Tokens};
+use super::clock::{cyclize, set_display_clock, Clock, Phase};
+use super::config::DramConfig;
+use super::event_log::EventLog;
+use super::image::ImageFormat;
+use super::modules::{ReadDone, Sent};
+use super::reorder_buffer::ReorderBuffer;
+use super::rng::seed_rng;
+use super::stats::{BankStats, ModuleStats, Stall};
+use super::watch::{WatchAction, WatchHit, WatchSpec};
+use memory_interface::trace::TraceWriter;
+use memory_interface::{BackendKind, MemoryError, MemoryInterface};
+use std::any::Any;
+use std::cell::RefCell;
 use std::collections::VecDeque;
-use std::sync::Arc;
+use std::error::Error;
+use std::rc::Rc;
+
+// The state of an elaborated design: its arrays, FIFOs and the values its
+// modules expose downstream. The simulator only reaches it through here,
+// the design's own modules share it directly.
+pub trait Design {
+    // land the array writes and FIFO pushes and pops due at the stamp
+    fn tick_registers(&mut self, stamp: usize);
+    // earliest pending array write or FIFO push or pop
+    fn next_event(&self) -> Option<usize>;
+    fn array_word(&self, name: &str, addr: usize) -> Option<u64>;
+    fn add_watch(&mut self, spec: WatchSpec) -> Result<(), String>;
+    fn remove_watch(&mut self, label: &str) -> bool;
+    fn watch_labels(&self) -> Vec<String>;
+    fn take_watch_hits(&mut self) -> Vec<WatchHit>;
+    fn print_fifo(&self, name: &str, stamp: usize) -> bool;
+    fn load_array(&mut self, name: &str, path: &str, format: ImageFormat) -> Result<(), String>;
+    fn dump_array(&self, name: &str, path: &str, format: ImageFormat) -> Result<(), String>;
+    fn save(&self, out: &mut String);
+    fn restore(&mut self, t: &mut Tokens) -> Result<(), CheckpointError>;
+    fn as_any(&mut self) -> &mut dyn Any;
+}
+
+// What a simulator holds before a design is elaborated into it.
+pub struct NoDesign;
+
+impl Design for NoDesign {
+    fn tick_registers(&mut self, _stamp: usize) {}
+    fn next_event(&self) -> Option<usize> {
+        None
+    }
+    fn array_word(&self, _name: &str, _addr: usize) -> Option<u64> {
+        None
+    }
+    fn add_watch(&mut self, spec: WatchSpec) -> Result<(), String> {
+        Err(format!("no array or FIFO named {}", spec.target))
+    }
+    fn remove_watch(&mut self, _label: &str) -> bool {
+        false
+    }
+    fn watch_labels(&self) -> Vec<String> {
+        Vec::new()
+    }
+    fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        Vec::new()
+    }
+    fn print_fifo(&self, _name: &str, _stamp: usize) -> bool {
+        false
+    }
+    fn load_array(&mut self, name: &str, _path: &str, _format: ImageFormat) -> Result<(), String> {
+        Err(format!("no array named {}", name))
+    }
+    fn dump_array(&self, name: &str, _path: &str, _format: ImageFormat) -> Result<(), String> {
+        Err(format!("no array named {}", name))
+    }
+    fn save(&self, _out: &mut String) {}
+    fn restore(&mut self, _t: &mut Tokens) -> Result<(), CheckpointError> {
+        Ok(())
+    }
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub trait Module {
+    fn name(&self) -> &'static str;
+    // Ok(false) when the module could not run this cycle, an error when the
+    // memory backend failed under it
+    fn evaluate(&self, sim: &mut Simulator) -> Result<bool, MemoryError>;
+    // modules triggered in the same cycle whenever this one is
+    fn downstreams(&self) -> &[&'static str] {
+        &[]
+    }
+    // clear the values this module exposes to its downstreams
+    fn reset(&self, _sim: &mut Simulator) {}
+    fn domain(&self) -> &'static str {
+        "core"
+    }
+}
+
+// A module elaborated into plain functions, the way generated designs are,
+// run on the design state S it shares with the other modules.
+pub struct ModuleDef<S> {
+    pub name: &'static str,
+    pub evaluate: fn(&mut Simulator, &mut S) -> Result<bool, MemoryError>,
+    pub reset: fn(&mut S),
+    pub downstreams: &'static [&'static str],
+    pub domain: &'static str,
+    pub state: Rc<RefCell<S>>,
+}
+
+impl<S> Module for ModuleDef<S> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn evaluate(&self, sim: &mut Simulator) -> Result<bool, MemoryError> {
+        (self.evaluate)(sim, &mut self.state.borrow_mut())
+    }
+    fn downstreams(&self) -> &[&'static str] {
+        self.downstreams
+    }
+    fn reset(&self, _sim: &mut Simulator) {
+        (self.reset)(&mut self.state.borrow_mut())
+    }
+    fn domain(&self) -> &'static str {
+        self.domain
+    }
+}
+
+pub struct ModuleSlot {
+    pub module: Rc<dyn Module>,
+    pub triggered: bool,
+    pub event: VecDeque<usize>,
+    pub upstreams: Vec<usize>,
+    pub domain: usize,
+    pub stats: ModuleStats,
+}
+
+impl ModuleSlot {
+    // modules without upstreams are driven by their own event queue
+    pub fn is_downstream(&self) -> bool {
+        !self.upstreams.is_empty()
+    }
+}
+
+pub struct Domain {
+    pub name: &'static str,
+    pub clock: Clock,
+}
 
 pub struct Simulator {
     pub stamp: usize,
-    pub array_2a959: Array<i32>, 
-    pub array_2aa85: Array<u32>,
-    pub MemUser_57a15_triggered: bool,
-    pub MemUser_57a15_event: VecDeque<usize>,
-    pub MemUser_57a15_rdata: FIFO<u32>,
-    pub Driver_triggered: bool,
-    pub Driver_event: VecDeque<usize>,
-    pub SRAM_2a9ed_triggered: bool,
-    pub _2a969_value: Option<bool>,
-    pub _2aa41_value: Option<i16>,
-    pub _2a991_value: Option<bool>,
-    pub _2aa91_value: Option<u32>,
-    pub mem_interface: Arc<MemoryInterface<'static>>,
-    _lib: Arc<Library>,
+    pub clock: Clock, // clock of the domain being evaluated, else the core's
+    pub domains: Vec<Domain>,
+    pub mem_clock: Clock,
+    pub mem_ticked: usize, // stamp of the last memory tick
+    pub design: Rc<RefCell<dyn Design>>,
+    pub mem_reads: usize, // reads of any kind the backend still owes
+    pub mem_done: Rc<RefCell<VecDeque<ReadDone>>>, // and those it finished in the last tick
+    pub mem_sent: Vec<Sent>, // every request memory accepted, for checkpoints
+    pub rob: ReorderBuffer<u32>,
+    pub cache: Option<Cache>,
+    pub addr_map: AddrMapper,
+    pub banks: Vec<BankStats>,
+    pub traffic: VecDeque<i64>, // generated reads not yet issued
+    pub traffic_outstanding: usize,
+    pub log: EventLog,
+    pub watch_hits: Vec<WatchHit>,
+    stall: Option<Stall>,
+    pub mem_refused: bool, // a request bounced since the last memory tick
+    pub idle_count: usize,
+    pub evaluated: usize,
+    pub every_cycle: bool, // step every edge, not only those with work pending
+    pub modules: Vec<ModuleSlot>,
+    order: Vec<usize>,
+    pub mem_interface: Rc<MemoryInterface<'static>>,
 }
 
 impl Simulator {
-    pub fn new() -> Self {
-        let lib = Arc::new(
-            unsafe { Library::new("/tmp/memory_simulator/src/libwrapper.so") }
-                .expect("Failed to load library"),
-        );
-        let lib_ref = lib.clone();
-
-        let mem = unsafe {
-            let lib_static = Box::leak(Box::new(lib.clone()));
-
-            Arc::new(MemoryInterface::new(&*lib_static).expect("Failed to create MemoryInterface"))
-        };
-        Simulator {
+    pub fn new(backend: BackendKind) -> Result<Self, Box<dyn Error>> {
+        let mem = Rc::new(unsafe { MemoryInterface::open_backend(backend)? });
+        Ok(Simulator {
             stamp: 0,
-            array_2a959: Array::new(1), // cycle
-            array_2aa85: Array::new(512),  // sram size
-            MemUser_57a15_triggered: false,
-            MemUser_57a15_event: VecDeque::new(),
-            MemUser_57a15_rdata: FIFO::new(), // read data
-            Driver_triggered: false,
-            Driver_event: VecDeque::new(),
-            SRAM_2a9ed_triggered: false,
-            _2a969_value: None,
-            _2aa41_value: None,
-            _2a991_value: None,
-            _2aa91_value: None,
+            clock: Clock::default(),
+            domains: vec![Domain {
+                name: "core",
+                clock: Clock::default(),
+            }],
+            mem_clock: Clock::default(),
+            mem_ticked: 0,
+            design: Rc::new(RefCell::new(NoDesign)),
+            mem_reads: 0,
+            mem_done: Rc::default(),
+            mem_sent: Vec::new(),
+            rob: ReorderBuffer::new(16, true),
+            cache: None,
+            addr_map: AddrMapper::new("DDR4_8Gb_x8", 1, 2).unwrap(),
+            banks: Vec::new(),
+            traffic: VecDeque::new(),
+            traffic_outstanding: 0,
+            log: EventLog::new(false),
+            watch_hits: Vec::new(),
+            stall: None,
+            mem_refused: false,
+            idle_count: 0,
+            evaluated: 0,
+            every_cycle: false,
+            modules: Vec::new(),
+            order: Vec::new(),
             mem_interface: mem,
-            _lib: lib,
+        })
+    }
+
+    pub fn set_core_clock(&mut self, clock: Clock) {
+        self.domains[0].clock = clock;
+        self.clock = clock;
+    }
+
+    pub fn add_domain(&mut self, name: &'static str, clock: Clock) {
+        if self.domains.iter().any(|d| d.name == name) {
+            panic!("Clock domain {} added twice!", name);
         }
+        self.domains.push(Domain { name, clock });
     }
 
-    pub fn print_rdata_state(&mut self) {
-        println!("\n=== MemUser_57a15_rdata State at {} ===", cyclize(self.stamp));
-        
-        // Print payload
-        println!("Payload Queue:");
-        if self.MemUser_57a15_rdata.payload.is_empty() {
-            println!("  [Empty]");
-        } else {
-            for (i, value) in self.MemUser_57a15_rdata.payload.iter().enumerate() {
-                println!("  [{:2}]: {:?}", i, value);
+    pub fn register(&mut self, module: Rc<dyn Module>) -> usize {
+        if self.modules.iter().any(|m| m.module.name() == module.name()) {
+            panic!("Module {} registered twice!", module.name());
+        }
+        self.modules.push(ModuleSlot {
+            module,
+            triggered: false,
+            event: VecDeque::new(),
+            upstreams: Vec::new(),
+            domain: 0,
+            stats: ModuleStats::default(),
+        });
+        self.order.clear();
+        self.modules.len() - 1
+    }
+
+    pub fn module_id(&self, name: &str) -> usize {
+        self.modules
+            .iter()
+            .position(|m| m.module.name() == name)
+            .unwrap_or_else(|| panic!("Unknown module {}!", name))
+    }
+
+    // Queue an event for an event-driven module.
+    pub fn schedule(&mut self, name: &str, stamp: usize) {
+        let id = self.module_id(name);
+        self.modules[id].event.push_back(stamp);
+        self.modules[id].stats.events_queued += 1;
+    }
+
+    // Called by a module right before it fails, to say what it waits on.
+    pub fn stall(&mut self, reason: Stall) {
+        self.mem_refused |= reason == Stall::MemBackpressure;
+        self.stall = Some(reason);
+    }
+
+    pub fn set_addr_map(&mut self, addr_map: AddrMapper) {
+        self.banks = vec![BankStats::default(); addr_map.num_banks()];
+        self.addr_map = addr_map;
+    }
+
+    // Account a DRAM access to its bank, latency given for completed reads.
+    pub fn record_bank(&mut self, addr: i64, latency: Option<usize>) {
+        let v = self.addr_map.map(addr);
+        let id = self.addr_map.bank_id(&v);
+        if let Some(bank) = self.banks.get_mut(id) {
+            bank.record(v.row, latency);
+        }
+    }
+
+    pub fn triggered(&self, name: &str) -> bool {
+        self.modules[self.module_id(name)].triggered
+    }
+
+    // Resolve downstream names and order the modules so that every module is
+    // evaluated after all of its upstreams. Registration order breaks ties.
+    fn elaborate(&mut self) {
+        for slot in self.modules.iter_mut() {
+            slot.upstreams.clear();
+            let domain = slot.module.domain();
+            slot.domain = self
+                .domains
+                .iter()
+                .position(|d| d.name == domain)
+                .unwrap_or_else(|| {
+                    panic!("Module {} is in unknown clock domain {}!", slot.module.name(), domain)
+                });
+        }
+        for id in 0..self.modules.len() {
+            let downstreams: Vec<&'static str> = self.modules[id].module.downstreams().to_vec();
+            for name in downstreams {
+                let ds = self.module_id(name);
+                if self.modules[ds].domain != self.modules[id].domain {
+                    panic!(
+                        "Module {} drives {} across clock domains, use an AsyncFIFO!",
+                        self.modules[id].module.name(),
+                        name
+                    );
+                }
+                self.modules[ds].upstreams.push(id);
             }
         }
 
-        // Print push queue (XEQ<FIFOPush>)
-        println!("\nPush Queue (XEQ):");
-        if self.MemUser_57a15_rdata.push.is_empty() {
-            println!("  [Empty]");
-        } else {
-            // Print each push event's cycle and pusher
-            for (cycle, event) in self.MemUser_57a15_rdata.push.q.iter() {
-                println!("  Cycle: {}", cyclize(*cycle));
+        let mut indegree: Vec<usize> = self.modules.iter().map(|m| m.upstreams.len()).collect();
+        let mut order = Vec::new();
+        while order.len() < self.modules.len() {
+            let next = (0..self.modules.len()).find(|id| indegree[*id] == 0 && !order.contains(id));
+            let Some(id) = next else {
+                let stuck: Vec<&str> = (0..self.modules.len())
+                    .filter(|id| !order.contains(id))
+                    .map(|id| self.modules[id].module.name())
+                    .collect();
+                panic!("Combinational loop between modules {:?}!", stuck);
+            };
+            for name in self.modules[id].module.downstreams() {
+                indegree[self.module_id(name)] -= 1;
             }
+            order.push(id);
         }
+        self.order = order;
+    }
+
+    pub fn array_word(&self, name: &str, addr: usize) -> Option<u64> {
+        self.design.borrow().array_word(name, addr)
+    }
+
+    pub fn add_watch(&mut self, spec: WatchSpec) -> Result<(), String> {
+        self.design.borrow_mut().add_watch(spec)
+    }
+
+    pub fn remove_watch(&mut self, label: &str) -> bool {
+        self.design.borrow_mut().remove_watch(label)
+    }
 
-        // Print pop queue (XEQ<FIFOPop>)
-        println!("\nPop Queue (XEQ):");
-        if self.MemUser_57a15_rdata.pop.is_empty() {
-            println!("  [Empty]");
+    pub fn watch_labels(&self) -> Vec<String> {
+        self.design.borrow().watch_labels()
+    }
+
+    // Gather the watches hit by the last register commit.
+    fn collect_watch_hits(&mut self) {
+        self.watch_hits = self.design.borrow_mut().take_watch_hits();
+        self.watch_hits.sort_by_key(|hit| hit.cycle);
+        for hit in self.watch_hits.iter() {
+            println!("{}", hit);
+        }
+    }
+
+    pub fn watch_break(&self) -> bool {
+        self.watch_hits
+            .iter()
+            .any(|hit| hit.action == WatchAction::Break)
+    }
+
+    pub fn print_fifo(&self, name: &str) -> bool {
+        self.design.borrow().print_fifo(name, self.stamp)
+    }
+
+    // The scheduler only needs to stop at memory edges while reads or cache
+    // traffic are in flight, or while the backend refuses requests and must
+    // drain. The edges it skips are still ticked, by catch_up_memory.
+    fn mem_next_event(&self) -> Option<usize> {
+        let cache_busy = self.cache.as_ref().is_some_and(|c| c.busy());
+        if self.mem_reads > 0
+            || cache_busy
+            || self.mem_refused
+        {
+            Some(self.mem_clock.next_edge(self.stamp, Phase::Commit))
         } else {
-            // Print each pop event's cycle and pusher
-            for (cycle, event) in self.MemUser_57a15_rdata.pop.q.iter() {
-                println!("  Cycle: {}", cyclize(*cycle));
-            }
+            None
         }
-        println!("=====================================\n");
+    }
+
+    // Earliest stamp at which anything is pending, across module events,
+    // register writes, FIFO push/pop and the memory backend.
+    pub fn next_event(&self) -> Option<usize> {
+        let events = self.modules.iter().map(|m| m.event.front().copied());
+        [self.design.borrow().next_event(), self.mem_next_event()]
+            .into_iter()
+            .chain(events)
+            .flatten()
+            .min()
     }
 
     fn event_valid(&self, event: &VecDeque<usize>) -> bool {
-        event.front().map_or(false, |x| *x <= self.stamp)
-    }
-
-    pub fn reset_downstream(&mut self) {
-        self.MemUser_57a15_triggered = false;
-        self.Driver_triggered = false;
-        self.SRAM_2a9ed_triggered = false;
-        self._2a969_value = None;
-        self._2aa41_value = None;
-        self._2a991_value = None;
-        self._2aa91_value = None;
-    }
-
-    pub fn tick_registers(&mut self) {
-        // self.array_2a959.tick(self.stamp); // the cycle
-        // self.array_2aa85.tick(self.stamp); // the SRAM
-        // self.MemUser_57a15_rdata.tick(self.stamp); // FIFO
-        // Pre-check FIFO state before ticking
-        // if !self.MemUser_57a15_rdata.payload.is_empty() || 
-        //    !self.MemUser_57a15_rdata.push.is_empty() {
-        //     self.MemUser_57a15_rdata.tick(self.stamp);
-        // }
-        
-        // // Only tick arrays if they have pending writes
-        
-        self.array_2a959.tick(self.stamp);
-        
-        //we tick that one into the payload when the write is not empty.
-        if !self.array_2aa85.write.is_empty() {
-            //println!("write is not empty, put the value into payload at cycle:{}", (self.stamp - self.stamp % 100)/100);
-            self.array_2aa85.tick(self.stamp);
-            println!("now write finish his job.")
-        }
-        // Reset the write queues after ticking
-        // if !self.MemUser_57a15_rdata.push.is_empty() &&    
-        // !self.MemUser_57a15_rdata.pop.is_empty() {
-            //println!("looks like u never reach here");
-         self.MemUser_57a15_rdata.tick(self.stamp);
-    // }
-    }
-
-    // simulate the memory user, 
-    fn simulate_MemUser_57a15(&mut self) {
-        //println!("Simulating MemUser_57a15 at cycle {}", self.stamp);
-        if self.event_valid(&self.MemUser_57a15_event) {
-            let succ = super::modules::MemUser_57a15(self);
-            //println!("memuser success or not: {}", succ);
-            if succ {
-                self.MemUser_57a15_event.pop_front();
-            } else {
+        event.front().is_some_and(|x| *x <= self.stamp)
+    }
+
+    pub fn reset_downstream(&mut self, domains: &[usize]) {
+        for id in 0..self.modules.len() {
+            if !domains.contains(&self.modules[id].domain) {
+                continue;
             }
-            self.MemUser_57a15_triggered = succ;
-        } // close event condition
-    } // close function
-
-    fn simulate_Driver(&mut self) {
-        //println!("Simulating Driver at cycle {}", self.stamp);
-        if self.event_valid(&self.Driver_event) {
-            //println!("Driver event at cycle {}", self.stamp);
-            let succ = super::modules::Driver(self);
-            if succ {
-                self.Driver_event.pop_front();
-            } else {
-                self._2a969_value = None;
-                self._2aa41_value = None;
-                self._2a991_value = None;
-                self._2aa91_value = None;
+            self.modules[id].triggered = false;
+            let module = self.modules[id].module.clone();
+            module.reset(self);
+        }
+    }
+
+    fn simulate_module(&mut self, id: usize) -> Result<(), MemoryError> {
+        let slot = &self.modules[id];
+        let ready = if slot.is_downstream() {
+            slot.upstreams.iter().any(|up| self.modules[*up].triggered)
+        } else {
+            self.event_valid(&slot.event)
+        };
+        if !ready {
+            return Ok(());
+        }
+        let module = slot.module.clone();
+        let succ = module.evaluate(self)?;
+        if let Some(stall) = self.stall.take() {
+            self.modules[id].stats.record_stall(stall);
+        }
+        if succ {
+            self.modules[id].stats.triggered += 1;
+            self.log.record(self.stamp, format!("trigger {}", module.name()));
+            if !self.modules[id].is_downstream() {
+                self.modules[id].event.pop_front();
             }
-            self.Driver_triggered = succ;
-        } // close event condition
-    } // close function
-
-    fn simulate_SRAM_2a9ed(&mut self) {
-        //println!("Simulating SRAM_2a9ed at cycle {}", self.stamp);
-        if self.Driver_triggered {
-            let succ = super::modules::SRAM_2a9ed(self);
-            self.SRAM_2a9ed_triggered = succ;
-        } // close event condition
-    } // close function
-}
+        } else {
+            module.reset(self);
+        }
+        self.modules[id].triggered = succ;
+        Ok(())
+    }
 
-pub fn simulate() {
-    unsafe {
-        let mut sim = Simulator::new();
-        unsafe {
-            sim.mem_interface
-                .init("/tmp/memory_simulator/config/example_config.yaml");
-        }
-        let simulators: Vec<fn(&mut Simulator)> = vec![
-            Simulator::simulate_MemUser_57a15,
-            Simulator::simulate_Driver,
-        ];
-        let downstreams: Vec<fn(&mut Simulator)> = vec![Simulator::simulate_SRAM_2a9ed];
-
-        for i in 1..=200 {
-            sim.Driver_event.push_back(i * 100); // stamp is 100 to 20000, totally 200 cycles
-        }
-        let mut idle_count = 0;
-        for i in 1..=200 {
-            sim.stamp = i * 100; // starts from the cycle one.
-            sim.reset_downstream();
-
-            for simulate in simulators.iter() {
-                simulate(&mut sim);
+    // Evaluate the modules of the given domains at the current stamp,
+    // returning whether any event-driven module triggered.
+    pub fn evaluate_cycle(&mut self, domains: &[usize]) -> Result<bool, MemoryError> {
+        if self.order.len() != self.modules.len() {
+            self.elaborate();
+        }
+        self.reset_downstream(domains);
+        for i in 0..self.order.len() {
+            let id = self.order[i];
+            let domain = self.modules[id].domain;
+            if domains.contains(&domain) {
+                self.clock = self.domains[domain].clock;
+                self.simulate_module(id)?;
             }
+        }
+        self.clock = self.domains[0].clock;
+        Ok(self
+            .modules
+            .iter()
+            .any(|m| domains.contains(&m.domain) && !m.is_downstream() && m.triggered))
+    }
+
+    fn tick_memory(&mut self, stamp: usize) -> Result<(), MemoryError> {
+        self.mem_ticked = stamp;
+        self.mem_interface.frontend_tick()?;
+        self.mem_interface.memory_tick()?;
+        super::modules::finish_reads(self);
+        Ok(())
+    }
 
-            for simulate in downstreams.iter() {
-                simulate(&mut sim);
+    // Tick the memory edges before the current stamp that the scheduler
+    // jumped over. Nothing calls back then, no read is in flight, but the
+    // DRAM keeps its clock: it refreshes and drains the writes it queued.
+    fn catch_up_memory(&mut self) -> Result<(), MemoryError> {
+        loop {
+            let edge = self.mem_clock.next_edge(self.mem_ticked, Phase::Commit);
+            if edge >= self.stamp {
+                return Ok(());
             }
+            self.mem_interface.set_stamp(edge as u64);
+            self.tick_memory(edge)?;
+        }
+    }
+
+    // Jump to the next clock edge, of any domain or the memory, at which
+    // work is pending, and run it: evaluation on evaluate edges, register
+    // commit and memory tick on commit edges. Returns false once the run is
+    // over, an error if the memory backend failed, after which it is unusable.
+    pub fn step(&mut self) -> Result<bool, MemoryError> {
+        // stepping every edge runs until the idle threshold, like the fixed
+        // steps the scheduler replaced, to check it against
+        let target = match self.next_event() {
+            _ if self.every_cycle => self.stamp + 1,
+            Some(next) => next.max(self.stamp + 1),
+            None => return Ok(false),
+        };
+        if self.order.len() != self.modules.len() {
+            self.elaborate();
+        }
+        self.stamp = self
+            .domains
+            .iter()
+            .map(|d| d.clock)
+            .chain(std::iter::once(self.mem_clock))
+            .map(|clock| clock.edge_at_or_after(target))
+            .min()
+            .unwrap();
+        self.catch_up_memory()?;
+        self.mem_interface.set_stamp(self.stamp as u64);
+        self.watch_hits.clear();
 
-            let any_module_triggered = sim.MemUser_57a15_triggered || sim.Driver_triggered;
+        let evaluating: Vec<usize> = (0..self.domains.len())
+            .filter(|d| self.domains[*d].clock.is_edge(self.stamp, Phase::Evaluate))
+            .collect();
+        if !evaluating.is_empty() {
+            self.evaluated += 1;
+            let any_module_triggered = self.evaluate_cycle(&evaluating)?;
 
             // Handle idle threshold
             if !any_module_triggered {
-                idle_count += 1;
-                if idle_count >= 200 {
+                self.idle_count += 1;
+                if self.idle_count >= 200 {
                     println!("Simulation stopped due to reaching idle threshold of 200");
-                    break;
+                    return Ok(false);
                 }
             } else {
-                idle_count = 0;
+                self.idle_count = 0;
             }
+        }
+
+        if self
+            .domains
+            .iter()
+            .any(|d| d.clock.is_edge(self.stamp, Phase::Commit))
+        {
+            self.design.borrow_mut().tick_registers(self.stamp);
+            self.collect_watch_hits();
+        }
+        if self.mem_clock.is_edge(self.stamp, Phase::Commit) {
+            super::modules::issue_misses(self)?;
+            self.mem_refused = false;
+            self.tick_memory(self.stamp)?;
+        }
+        Ok(true)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct SimOptions {
+    pub checkpoint: Option<(usize, String)>, // cycle and path to save at
+    pub restore: Option<String>,
+    pub seed: u64,
+    pub keep_log: bool,
+    pub debug: bool,
+    pub watches: Vec<String>,
+    pub loads: Vec<(String, ImageFormat, String)>, // array, format and path
+    pub dumps: Vec<(String, ImageFormat, String)>, // written once the run ends
+    pub stats_json: Option<String>,
+    pub clock_period: Option<usize>,
+    pub core_mhz: Option<f64>, // derive the memory clock from tCK
+    pub rob_depth: Option<usize>,
+    pub out_of_order: bool,
+    pub every_cycle: bool, // to check the scheduler against
+    pub cache: Option<CacheConfig>,
+    pub traffic: Option<(TrafficPattern, usize)>, // extra reads and how many
+    pub dram: DramConfig,
+    pub backend: Option<BackendKind>, // the preferred one if not given
+    pub mem_trace: Option<String>,    // .csv for text, binary otherwise
+}
 
-            sim.stamp += 50;
-            sim.tick_registers();
-            unsafe {
-                sim.mem_interface.frontend_tick();
-                sim.mem_interface.memory_tick();
+pub fn simulate(opts: &SimOptions) -> Result<EventLog, String> {
+    seed_rng(opts.seed);
+    let backend = opts.backend.unwrap_or_else(BackendKind::preferred);
+    let mut sim = Simulator::new(backend).map_err(|e| format!("failed to open the {} backend: {}", backend, e))?;
+    sim.log = EventLog::new(opts.keep_log);
+    if let Some(period) = opts.clock_period {
+        sim.set_core_clock(Clock::new(period));
+    }
+    sim.mem_clock = sim.clock;
+    set_display_clock(sim.clock);
+    sim.every_cycle = opts.every_cycle;
+    sim.rob = ReorderBuffer::new(opts.rob_depth.unwrap_or(16), !opts.out_of_order);
+    if let Some(config) = &opts.cache {
+        println!("Cache: {} ({} sets)", config, config.sets());
+        sim.cache = Some(Cache::new(config.clone()));
+    }
+    println!("Memory backend: {}", sim.mem_interface.backend_name());
+    sim.mem_interface
+        .init_from_string(&opts.dram.to_yaml())
+        .map_err(|e| format!("failed to pass the DRAM config to the backend: {}", e))?;
+    let addr_map = AddrMapper::from_config(&opts.dram)?;
+    println!(
+        "Address mapping: RoBaRaCoCh on {}, {} banks",
+        addr_map.preset,
+        addr_map.num_banks()
+    );
+    sim.set_addr_map(addr_map);
+    if let Some((pattern, count)) = opts.traffic {
+        sim.traffic = pattern.generate(&sim.addr_map, count).into();
+    }
+    if let Some(mhz) = opts.core_mhz {
+        let tck = sim
+            .mem_interface
+            .tck()
+            .map_err(|e| format!("failed to read tCK from the backend: {}", e))? as f64;
+        let period = sim.clock.period as f64 * tck * mhz / 1000.0;
+        sim.mem_clock = Clock::new(((period / 2.0).round() as usize * 2).max(2));
+        println!(
+            "Memory clock: tCK {} ns, period {} against {} for the core at {} MHz",
+            tck, sim.mem_clock.period, sim.clock.period, mhz
+        );
+    }
+    super::modules::elaborate(&mut sim);
+    if let Some(path) = &opts.restore {
+        sim.restore_checkpoint(path).map_err(|e| format!("{}: {}", path, e))?;
+        println!("Restored checkpoint {} at {}", path, cyclize(sim.stamp));
+    }
+    // after the restore, so the replayed requests are not traced twice
+    if let Some(path) = &opts.mem_trace {
+        let writer = TraceWriter::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        sim.mem_interface.set_trace(writer);
+    }
+
+    for (name, format, path) in opts.loads.iter() {
+        sim.design
+            .borrow_mut()
+            .load_array(name, path, *format)
+            .map_err(|e| format!("failed to load {} from {}: {}", name, path, e))?;
+    }
+
+    let restored = sim.watch_labels();
+    for watch in opts.watches.iter().filter(|w| !restored.contains(w)) {
+        WatchSpec::parse(watch, WatchAction::Log)
+            .and_then(|spec| sim.add_watch(spec))
+            .map_err(|e| format!("invalid watch `{}`: {}", watch, e))?;
+    }
+
+    let failed = |sim: &Simulator, e: MemoryError| format!("DRAM backend failed at {}: {}", cyclize(sim.stamp), e);
+    if opts.debug {
+        super::debugger::repl(&mut sim).map_err(|e| failed(&sim, e))?;
+    } else {
+        let mut checkpoint = opts.checkpoint.clone();
+        while sim.step().map_err(|e| failed(&sim, e))? {
+            if sim.watch_break() {
+                println!("Simulation stopped by watchpoint");
+                break;
+            }
+            if let Some((cycle, path)) = &checkpoint {
+                if sim.clock.cycle(sim.stamp) >= *cycle {
+                    sim.save_checkpoint(path)
+                        .map_err(|e| format!("failed to save checkpoint {}: {}", path, e))?;
+                    println!("Saved checkpoint {} at {}", path, cyclize(sim.stamp));
+                    checkpoint = None;
+                }
             }
-            //sim.print_rdata_state();
+        }
+    }
+    println!(
+        "Simulation finished at {}, {} cycles evaluated",
+        cyclize(sim.stamp),
+        sim.evaluated
+    );
+    super::stats::print_report(&sim);
+    for (name, format, path) in opts.dumps.iter() {
+        match sim.design.borrow().dump_array(name, path, *format) {
+            Ok(()) => println!("Dumped {} to {} ({})", name, path, format.name()),
+            Err(e) => println!("Failed to dump {} to {}: {}", name, path, e),
+        }
+    }
+    if let Some(path) = &opts.mem_trace {
+        match sim.mem_interface.take_trace() {
+            Ok(Some(writer)) => println!("Memory trace: {} records in {}", writer.records, path),
+            Ok(None) => println!("Memory trace {} is incomplete", path),
+            Err(e) => println!("Failed to write memory trace {}: {}", path, e),
+        }
+    }
+    if let Some(path) = &opts.stats_json {
+        if let Err(e) = super::stats::export_json(&sim, path) {
+            println!("Failed to write statistics to {}: {}", path, e);
+        }
+    }
+    println!(
+        "Event log: {} events, hash {:016x} (seed {})",
+        sim.log.len(),
+        sim.log.hash(),
+        opts.seed
+    );
+    Ok(std::mem::replace(&mut sim.log, EventLog::new(false)))
+}
+
+// Run the simulation twice with the same seed and compare the event logs.
+pub fn verify_determinism(opts: &SimOptions) -> Result<bool, String> {
+    let mut opts_with_log = SimOptions {
+        keep_log: true,
+        ..opts.clone()
+    };
+    let first = simulate(&opts_with_log)?;
+    // restoring twice is fine, but the second run must not clobber the
+    // checkpoint the first one wrote
+    opts_with_log.checkpoint = None;
+    let second = simulate(&opts_with_log)?;
+    if first.hash() == second.hash() && first.lines() == second.lines() {
+        println!("Deterministic: both runs hash to {:016x}", first.hash());
+        return Ok(true);
+    }
+    let (a, b) = (first.lines(), second.lines());
+    let diverge = a.iter().zip(b.iter()).position(|(x, y)| x != y);
+    let at = diverge.unwrap_or(a.len().min(b.len()));
+    println!(
+        "Runs diverge at event {} (hashes {:016x} vs {:016x}):",
+        at,
+        first.hash(),
+        second.hash()
+    );
+    for i in at.saturating_sub(3)..(at + 3) {
+        let x = a.get(i).map_or("<end>", |l| l.as_str());
+        let y = b.get(i).map_or("<end>", |l| l.as_str());
+        let mark = if x == y { " " } else { "!" };
+        println!("{} {:>8}: {:<40} | {}", mark, i, x, y);
+    }
+    Ok(false)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    // records the order the modules run in
+    struct Probe {
+        name: &'static str,
+        downstreams: &'static [&'static str],
+        domain: &'static str,
+        ran: Ran,
+    }
+
+    impl Module for Probe {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn evaluate(&self, _sim: &mut Simulator) -> Result<bool, MemoryError> {
+            self.ran.borrow_mut().push(self.name);
+            Ok(true)
+        }
+        fn downstreams(&self) -> &[&'static str] {
+            self.downstreams
+        }
+        fn domain(&self) -> &'static str {
+            self.domain
+        }
+    }
+
+    type Ran = Rc<RefCell<Vec<&'static str>>>;
+
+    // name, downstreams and domain of each module
+    fn probes(modules: &[(&'static str, &'static [&'static str], &'static str)]) -> (Simulator, Ran) {
+        let mut sim = Simulator::new(BackendKind::Mock).unwrap();
+        let ran = Rc::new(RefCell::new(Vec::new()));
+        for &(name, downstreams, domain) in modules {
+            sim.register(Rc::new(Probe {
+                name,
+                downstreams,
+                domain,
+                ran: ran.clone(),
+            }));
+        }
+        (sim, ran)
+    }
+
+    #[test]
+    fn downstreams_run_after_their_upstreams() {
+        // registered against the order they must run in
+        let (mut sim, ran) = probes(&[
+            ("sink", &[], "core"),
+            ("middle", &["sink"], "core"),
+            ("other", &[], "core"),
+            ("source", &["middle", "sink"], "core"),
+        ]);
+        sim.elaborate();
+        let order: Vec<&str> = sim.order.iter().map(|id| sim.modules[*id].module.name()).collect();
+        assert_eq!(order, ["other", "source", "middle", "sink"]);
+        assert_eq!(sim.modules[sim.module_id("sink")].upstreams.len(), 2);
+
+        // only the event-driven modules with an event due start a cycle
+        sim.schedule("source", 0);
+        assert!(sim.evaluate_cycle(&[0]).unwrap());
+        assert_eq!(*ran.borrow(), ["source", "middle", "sink"]);
+        assert!(sim.triggered("sink"));
+        assert!(!sim.triggered("other"));
+    }
+
+    #[test]
+    fn untriggered_upstream_leaves_downstreams_idle() {
+        let (mut sim, ran) = probes(&[("source", &["sink"], "core"), ("sink", &[], "core")]);
+        assert!(!sim.evaluate_cycle(&[0]).unwrap());
+        assert!(ran.borrow().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Combinational loop between modules [\"a\", \"b\"]")]
+    fn combinational_loop() {
+        let (mut sim, _) = probes(&[("a", &["b"], "core"), ("b", &["a"], "core"), ("c", &[], "core")]);
+        sim.elaborate();
+    }
+
+    #[test]
+    #[should_panic(expected = "Combinational loop between modules [\"a\"]")]
+    fn module_driving_itself() {
+        let (mut sim, _) = probes(&[("a", &["a"], "core")]);
+        sim.elaborate();
+    }
+
+    #[test]
+    #[should_panic(expected = "Module a drives b across clock domains")]
+    fn edge_across_clock_domains() {
+        let (mut sim, _) = probes(&[("a", &["b"], "core"), ("b", &[], "mem")]);
+        sim.add_domain("mem", Clock::new(200));
+        sim.elaborate();
+    }
+
+    fn mock_run(core_mhz: Option<f64>) -> EventLog {
+        let opts = SimOptions {
+            keep_log: true,
+            core_mhz,
+            backend: Some(BackendKind::Mock),
+            ..SimOptions::default()
+        };
+        simulate(&opts).unwrap()
+    }
+
+    fn count(log: &EventLog, event: &str) -> usize {
+        log.lines().iter().filter(|line| line.ends_with(event)).count()
+    }
+
+    #[test]
+    fn same_period_keeps_driver_combinational() {
+        // the mock's tCK at 1200 MHz gives the memory the core's period
+        assert_eq!(mock_run(Some(1200.0)).hash(), mock_run(None).hash());
+    }
+
+    #[test]
+    fn crossing_clock_domains() {
+        for mhz in [600.0, 3600.0] {
+            let log = mock_run(Some(mhz));
+            assert_eq!(count(&log, "trigger Driver"), 200, "{} MHz", mhz);
+            assert_eq!(count(&log, "trigger SRAM_2a9ed"), 200, "{} MHz", mhz);
+            assert_eq!(count(&log, "trigger MemUser_57a15"), 100, "{} MHz", mhz);
+        }
+    }
+
+    // the default design alone, with a cache and traffic, and on both sides
+    // of a memory clock of its own
+    fn mock_configs() -> Vec<SimOptions> {
+        let mock = SimOptions {
+            backend: Some(BackendKind::Mock),
+            ..SimOptions::default()
+        };
+        vec![
+            mock.clone(),
+            SimOptions {
+                cache: Some(CacheConfig::parse("size=64,ways=2,line=8,mshrs=2,repl=random").unwrap()),
+                traffic: Some((TrafficPattern::parse("spread").unwrap(), 40)),
+                ..mock.clone()
+            },
+            // the traffic outlasts the design, only memory has work left
+            SimOptions {
+                out_of_order: true,
+                core_mhz: Some(600.0),
+                traffic: Some((TrafficPattern::parse("spread").unwrap(), 400)),
+                ..mock.clone()
+            },
+            SimOptions {
+                core_mhz: Some(3600.0),
+                ..mock
+            },
+        ]
+    }
+
+    #[test]
+    fn every_cycle_matches_event_driven() {
+        for (i, opts) in mock_configs().iter().enumerate() {
+            let event_driven = simulate(opts).unwrap().hash();
+            let every_cycle = simulate(&SimOptions {
+                every_cycle: true,
+                ..opts.clone()
+            })
+            .unwrap()
+            .hash();
+            assert_eq!(every_cycle, event_driven, "config {}", i);
+        }
+    }
+
+    #[test]
+    fn traffic_follows_the_seed() {
+        for pattern in ["conflict", "spread"] {
+            let run = |seed| {
+                let opts = SimOptions {
+                    seed,
+                    backend: Some(BackendKind::Mock),
+                    traffic: Some((TrafficPattern::parse(pattern).unwrap(), 40)),
+                    ..SimOptions::default()
+                };
+                simulate(&opts).unwrap().hash()
+            };
+            assert_eq!(run(1), run(1), "{}", pattern);
+            assert_ne!(run(1), run(2), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        for (i, opts) in mock_configs().iter().enumerate() {
+            let path = std::env::temp_dir().join(format!("round-trip-{}-{}.ckpt", std::process::id(), i));
+            let path = path.to_str().unwrap().to_string();
+            let straight = simulate(opts).unwrap().hash();
+            // mid-run, with requests still queued in the memory
+            let saved = simulate(&SimOptions {
+                checkpoint: Some((101, path.clone())),
+                ..opts.clone()
+            })
+            .unwrap();
+            let restored = simulate(&SimOptions {
+                restore: Some(path.clone()),
+                ..opts.clone()
+            });
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(saved.hash(), straight, "config {}", i);
+            assert_eq!(restored.unwrap().hash(), straight, "config {}", i);
         }
     }
 }
@@ -0,0 +1,184 @@
+use super::clock::cyclize;
+use super::simulator::Simulator;
+use std::fmt::Write;
+use std::fs;
+
+// Why a module failed to evaluate, reported by the module itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stall {
+    FifoEmpty,
+    MemBackpressure,
+}
+
+#[derive(Clone, Default)]
+pub struct ModuleStats {
+    pub triggered: usize,
+    pub fifo_empty: usize,
+    pub mem_blocked: usize,
+    pub events_queued: usize,
+}
+
+impl ModuleStats {
+    pub fn record_stall(&mut self, stall: Stall) {
+        match stall {
+            Stall::FifoEmpty => self.fifo_empty += 1,
+            Stall::MemBackpressure => self.mem_blocked += 1,
+        }
+    }
+}
+
+// Accesses seen by one DRAM bank. Reads are recorded as they complete,
+// writes as they issue since the backend does not call back for them.
+#[derive(Clone, Default)]
+pub struct BankStats {
+    pub reads: usize,
+    pub writes: usize,
+    pub read_latency: usize, // summed, in memory cycles
+    pub max_latency: usize,
+    pub row_switches: usize, // accesses to a different row than the last one
+    pub last_row: Option<usize>,
+}
+
+impl BankStats {
+    pub fn record(&mut self, row: usize, latency: Option<usize>) {
+        match latency {
+            Some(latency) => {
+                self.reads += 1;
+                self.read_latency += latency;
+                self.max_latency = self.max_latency.max(latency);
+            }
+            None => self.writes += 1,
+        }
+        if self.last_row.is_some_and(|last| last != row) {
+            self.row_switches += 1;
+        }
+        self.last_row = Some(row);
+    }
+
+    pub fn avg_latency(&self) -> f64 {
+        if self.reads == 0 {
+            0.0
+        } else {
+            self.read_latency as f64 / self.reads as f64
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.reads == 0 && self.writes == 0
+    }
+}
+
+pub fn print_report(sim: &Simulator) {
+    println!(
+        "{:<16} {:>10} {:>11} {:>11} {:>8}",
+        "module", "triggered", "fifo empty", "mem blocked", "queued"
+    );
+    for slot in sim.modules.iter() {
+        let stats = &slot.stats;
+        println!(
+            "{:<16} {:>10} {:>11} {:>11} {:>8}",
+            slot.module.name(),
+            stats.triggered,
+            stats.fifo_empty,
+            stats.mem_blocked,
+            stats.events_queued
+        );
+    }
+    if let Some(cache) = &sim.cache {
+        let stats = &cache.stats;
+        println!(
+            "cache: reads {} hit / {} miss, writes {} hit / {} miss, hit rate {:.3}",
+            stats.read_hits,
+            stats.read_misses,
+            stats.write_hits,
+            stats.write_misses,
+            stats.hit_rate()
+        );
+        println!(
+            "cache: {} MSHR merges, {} MSHR full, {} fills, {} write-backs",
+            stats.mshr_merges, stats.mshr_full, stats.fills, stats.writebacks
+        );
+    }
+    if sim.banks.iter().all(|b| b.is_idle()) {
+        return;
+    }
+    println!(
+        "{:<16} {:>6} {:>7} {:>12} {:>12} {:>13}",
+        "bank", "reads", "writes", "avg latency", "max latency", "row switches"
+    );
+    for (id, bank) in sim.banks.iter().enumerate().filter(|(_, b)| !b.is_idle()) {
+        println!(
+            "{:<16} {:>6} {:>7} {:>12.1} {:>12} {:>13}",
+            sim.addr_map.bank_name(id),
+            bank.reads,
+            bank.writes,
+            bank.avg_latency(),
+            bank.max_latency,
+            bank.row_switches
+        );
+    }
+}
+
+pub fn to_json(sim: &Simulator) -> String {
+    let mut out = String::new();
+    writeln!(out, "{{").unwrap();
+    writeln!(out, "  \"end\": \"{}\",", cyclize(sim.stamp)).unwrap();
+    writeln!(out, "  \"cycles_evaluated\": {},", sim.evaluated).unwrap();
+    writeln!(out, "  \"modules\": {{").unwrap();
+    for (i, slot) in sim.modules.iter().enumerate() {
+        let stats = &slot.stats;
+        write!(
+            out,
+            "    \"{}\": {{\"triggered\": {}, \"fifo_empty\": {}, \"mem_blocked\": {}, \"events_queued\": {}}}",
+            slot.module.name(),
+            stats.triggered,
+            stats.fifo_empty,
+            stats.mem_blocked,
+            stats.events_queued
+        )
+        .unwrap();
+        out.push_str(if i + 1 < sim.modules.len() { ",\n" } else { "\n" });
+    }
+    writeln!(out, "  }},").unwrap();
+    if let Some(cache) = &sim.cache {
+        let stats = &cache.stats;
+        writeln!(
+            out,
+            "  \"cache\": {{\"config\": \"{}\", \"read_hits\": {}, \"read_misses\": {}, \"write_hits\": {}, \"write_misses\": {}, \"mshr_merges\": {}, \"mshr_full\": {}, \"fills\": {}, \"writebacks\": {}}},",
+            cache.config,
+            stats.read_hits,
+            stats.read_misses,
+            stats.write_hits,
+            stats.write_misses,
+            stats.mshr_merges,
+            stats.mshr_full,
+            stats.fills,
+            stats.writebacks
+        )
+        .unwrap();
+    }
+    writeln!(out, "  \"banks\": {{").unwrap();
+    let active: Vec<(usize, &BankStats)> =
+        sim.banks.iter().enumerate().filter(|(_, b)| !b.is_idle()).collect();
+    for (i, (id, bank)) in active.iter().enumerate() {
+        write!(
+            out,
+            "    \"{}\": {{\"reads\": {}, \"writes\": {}, \"avg_latency\": {:.1}, \"max_latency\": {}, \"row_switches\": {}}}",
+            sim.addr_map.bank_name(*id),
+            bank.reads,
+            bank.writes,
+            bank.avg_latency(),
+            bank.max_latency,
+            bank.row_switches
+        )
+        .unwrap();
+        out.push_str(if i + 1 < active.len() { ",\n" } else { "\n" });
+    }
+    writeln!(out, "  }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+pub fn export_json(sim: &Simulator, path: &str) -> std::io::Result<()> {
+    fs::write(path, to_json(sim))
+}
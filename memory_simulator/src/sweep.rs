@@ -0,0 +1,229 @@
+use super::addr_map::{AddrMapper, TrafficPattern};
+use super::config::DramConfig;
+use super::rng::{seed_rng, with_rng};
+use memory_interface::{BackendKind, MemoryError, MemoryInterface, Request, RequestKind};
+use rand::Rng;
+use std::ffi::c_void;
+use std::fmt::Write;
+use std::fs;
+
+// Give up on a point whose reads have not all returned by then.
+const MAX_CYCLES: usize = 1_000_000;
+// Bytes moved by one request, a DDR4 burst.
+const REQUEST_BYTES: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Workload {
+    Stream,                  // consecutive bursts
+    Random,                  // uniform over 1 GiB, seeded
+    Pattern(TrafficPattern), // bank-targeted, see addr_map
+}
+
+impl Workload {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "stream" => Some(Workload::Stream),
+            "random" => Some(Workload::Random),
+            _ => TrafficPattern::parse(s).map(Workload::Pattern),
+        }
+    }
+
+    fn addresses(&self, config: &DramConfig, count: usize) -> Result<Vec<i64>, String> {
+        Ok(match self {
+            Workload::Stream => (0..count).map(|i| (i * REQUEST_BYTES) as i64).collect(),
+            Workload::Random => with_rng(|rng| {
+                (0..count)
+                    .map(|_| rng.gen_range(0..1i64 << 30) & !(REQUEST_BYTES as i64 - 1))
+                    .collect()
+            }),
+            Workload::Pattern(pattern) => pattern.generate(&AddrMapper::from_config(config)?, count),
+        })
+    }
+}
+
+// One axis of the grid per key, every combination of values is a point.
+#[derive(Clone, Default)]
+pub struct SweepOptions {
+    pub base: DramConfig,
+    pub axes: Vec<(String, Vec<String>)>,
+    pub workload: Option<Workload>,
+    pub count: usize,
+    pub seed: u64,
+    pub csv: Option<String>,
+    pub backend: Option<BackendKind>,
+}
+
+pub struct PointResult {
+    pub values: Vec<String>, // one per axis
+    pub reads: usize,
+    pub cycles: usize,
+    pub avg_latency: f64, // memory cycles
+    pub max_latency: usize,
+    pub bandwidth: f64, // GB/s
+}
+
+#[derive(Default)]
+struct Completions {
+    latencies: Vec<usize>,
+}
+
+fn sweep_callback(req: &Request, ctx: *mut c_void) {
+    let done: &mut Completions = unsafe { &mut *(ctx as *mut Completions) };
+    done.latencies.push((req.depart - req.arrive) as usize);
+}
+
+fn grid(axes: &[(String, Vec<String>)]) -> Vec<Vec<String>> {
+    let mut points = vec![Vec::new()];
+    for (_, values) in axes {
+        points = points
+            .into_iter()
+            .flat_map(|point| {
+                values.iter().map(move |v| {
+                    let mut point = point.clone();
+                    point.push(v.clone());
+                    point
+                })
+            })
+            .collect();
+    }
+    points
+}
+
+// Issue every read as soon as the backend takes it and tick until the last
+// one returns.
+unsafe fn run_point(
+    backend: BackendKind,
+    config: &DramConfig,
+    addrs: &[i64],
+) -> Result<(usize, f32, Completions), String> {
+    let mem = MemoryInterface::open_backend(backend).map_err(|e| e.to_string())?;
+    mem.init_from_string(&config.to_yaml()).map_err(|e| e.to_string())?;
+    let mut done = Completions::default();
+    let ctx = &mut done as *mut Completions as *mut c_void;
+    let mut next = 0;
+    let mut cycles = 0;
+    while done.latencies.len() < addrs.len() {
+        if cycles >= MAX_CYCLES {
+            return Err(format!("{} of {} reads returned", done.latencies.len(), addrs.len()));
+        }
+        while next < addrs.len() {
+            match mem.send_request(addrs[next], RequestKind::Read, sweep_callback, ctx) {
+                Ok(()) => next += 1,
+                Err(MemoryError::QueueFull) => break,
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        mem.frontend_tick().map_err(|e| e.to_string())?;
+        mem.memory_tick().map_err(|e| e.to_string())?;
+        cycles += 1;
+    }
+    Ok((cycles, mem.tck().map_err(|e| e.to_string())?, done))
+}
+
+fn header(key: &str) -> &str {
+    // the last two path components are enough to tell the axes apart
+    match key.rmatch_indices('.').nth(1) {
+        Some((i, _)) => &key[i + 1..],
+        None => key,
+    }
+}
+
+// Run every point of the grid, printing a row each. The points are all
+// checked first, so a typo in the last value does not surface after the
+// others have run.
+pub fn sweep(opts: &SweepOptions) -> Result<Vec<PointResult>, String> {
+    let points = grid(&opts.axes);
+    let mut configs = Vec::new();
+    for values in points.iter() {
+        let overrides: Vec<String> = opts
+            .axes
+            .iter()
+            .zip(values)
+            .map(|((key, _), value)| format!("{}={}", key, value))
+            .collect();
+        let mut config = opts.base.clone();
+        config
+            .merge(&overrides)
+            .map_err(|e| format!("{}: {}", overrides.join(" "), e))?;
+        configs.push(config);
+    }
+
+    let backend = opts.backend.unwrap_or_else(BackendKind::preferred);
+    if backend == BackendKind::Mock && !opts.axes.is_empty() {
+        // its latencies are fixed, every point would print the same row
+        return Err("the mock backend ignores the DRAM config, sweep needs a Ramulator backend".to_string());
+    }
+    println!("Memory backend: {}", backend);
+    let workload = opts.workload.unwrap_or(Workload::Stream);
+    let widths: Vec<usize> = opts
+        .axes
+        .iter()
+        .map(|(key, values)| {
+            let longest = values.iter().map(String::len).max().unwrap_or(0);
+            header(key).len().max(longest).max(12)
+        })
+        .collect();
+    for ((key, _), width) in opts.axes.iter().zip(widths.iter()) {
+        print!("{:<w$} ", header(key), w = width);
+    }
+    println!("{:>7} {:>8} {:>11} {:>11} {:>8}", "reads", "cycles", "avg latency", "max latency", "GB/s");
+
+    let mut results = Vec::new();
+    for (values, config) in points.into_iter().zip(configs.iter()) {
+        for (value, width) in values.iter().zip(widths.iter()) {
+            print!("{:<w$} ", value, w = width);
+        }
+        seed_rng(opts.seed);
+        let run = workload
+            .addresses(config, opts.count)
+            .and_then(|addrs| unsafe { run_point(backend, config, &addrs) });
+        let (cycles, tck, done) = match run {
+            Ok(run) => run,
+            Err(e) => {
+                println!("failed: {}", e);
+                continue;
+            }
+        };
+        let reads = done.latencies.len();
+        let result = PointResult {
+            values,
+            reads,
+            cycles,
+            avg_latency: done.latencies.iter().sum::<usize>() as f64 / reads.max(1) as f64,
+            max_latency: done.latencies.iter().copied().max().unwrap_or(0),
+            // a loop is one memory cycle, the clock ratios being fixed at 1,
+            // and bytes per ns is GB/s
+            bandwidth: (reads * REQUEST_BYTES) as f64 / (cycles as f64 * tck as f64),
+        };
+        println!(
+            "{:>7} {:>8} {:>11.1} {:>11} {:>8.2}",
+            result.reads, result.cycles, result.avg_latency, result.max_latency, result.bandwidth
+        );
+        results.push(result);
+    }
+
+    if let Some(path) = &opts.csv {
+        fs::write(path, to_csv(opts, &results)).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(results)
+}
+
+pub fn to_csv(opts: &SweepOptions, results: &[PointResult]) -> String {
+    let mut out = String::new();
+    for (key, _) in opts.axes.iter() {
+        write!(out, "{},", key).unwrap();
+    }
+    writeln!(out, "reads,cycles,avg_latency,max_latency,bandwidth_gbps").unwrap();
+    for r in results {
+        for value in r.values.iter() {
+            write!(out, "{},", value).unwrap();
+        }
+        writeln!(
+            out,
+            "{},{},{:.3},{},{:.3}",
+            r.reads, r.cycles, r.avg_latency, r.max_latency, r.bandwidth
+        )
+        .unwrap();
+    }
+    out
+}
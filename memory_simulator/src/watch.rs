@@ -0,0 +1,282 @@
+use super::clock::cyclize;
+use super::runtime::{Array, ValueCastTo, FIFO};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn parse(s: &str) -> Option<CmpOp> {
+        match s {
+            "==" => Some(CmpOp::Eq),
+            "!=" => Some(CmpOp::Ne),
+            "<" => Some(CmpOp::Lt),
+            "<=" => Some(CmpOp::Le),
+            ">" => Some(CmpOp::Gt),
+            ">=" => Some(CmpOp::Ge),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        }
+    }
+
+    fn eval(&self, lhs: i64, rhs: i64) -> bool {
+        match self {
+            CmpOp::Eq => lhs == rhs,
+            CmpOp::Ne => lhs != rhs,
+            CmpOp::Lt => lhs < rhs,
+            CmpOp::Le => lhs <= rhs,
+            CmpOp::Gt => lhs > rhs,
+            CmpOp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchAction {
+    Log,
+    Break,
+}
+
+// A watch on the writes to an array or the pushes to a FIFO, written as
+// `TARGET[ADDR] OP VALUE ACTION` where everything after TARGET is optional,
+// e.g. `array_2aa85[15] == 143 break` or `MemUser_57a15_rdata > 100`.
+#[derive(Clone, Debug)]
+pub struct WatchSpec {
+    pub target: String,
+    pub addr: Option<usize>,
+    pub cond: Option<(CmpOp, i64)>,
+    pub action: WatchAction,
+}
+
+impl WatchSpec {
+    pub fn parse(s: &str, default: WatchAction) -> Result<WatchSpec, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let Some((target, rest)) = words.split_first() else {
+            return Err("empty watch".to_string());
+        };
+        let (target, addr) = match target.split_once('[') {
+            Some((name, addr)) => {
+                let addr = addr
+                    .strip_suffix(']')
+                    .and_then(|a| a.parse().ok())
+                    .ok_or_else(|| format!("invalid address in `{}`", target))?;
+                (name, Some(addr))
+            }
+            None => (*target, None),
+        };
+        let (cond, rest) = match rest {
+            [op, value, rest @ ..] if CmpOp::parse(op).is_some() => {
+                let value = value
+                    .parse()
+                    .map_err(|_| format!("invalid value `{}`", value))?;
+                (Some((CmpOp::parse(op).unwrap(), value)), rest)
+            }
+            _ => (None, rest),
+        };
+        let action = match rest {
+            [] => default,
+            ["log"] => WatchAction::Log,
+            ["break"] => WatchAction::Break,
+            _ => return Err(format!("unexpected `{}`", rest.join(" "))),
+        };
+        Ok(WatchSpec {
+            target: target.to_string(),
+            addr,
+            cond,
+            action,
+        })
+    }
+
+    // Returns the watched value when it satisfies the condition.
+    fn matcher<T: ValueCastTo<i64>>(&self) -> Matcher<T> {
+        let cond = self.cond;
+        Box::new(move |data: &T| {
+            let value = ValueCastTo::<i64>::cast(data);
+            match cond {
+                Some((op, rhs)) if !op.eval(value, rhs) => None,
+                _ => Some(value),
+            }
+        })
+    }
+}
+
+impl fmt::Display for WatchSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.target)?;
+        if let Some(addr) = self.addr {
+            write!(f, "[{}]", addr)?;
+        }
+        if let Some((op, value)) = self.cond {
+            write!(f, " {} {}", op.symbol(), value)?;
+        }
+        match self.action {
+            WatchAction::Log => write!(f, " log"),
+            WatchAction::Break => write!(f, " break"),
+        }
+    }
+}
+
+pub struct WatchHit {
+    pub watch: String,
+    pub action: WatchAction,
+    pub cycle: usize,
+    pub addr: Option<usize>,
+    pub value: i64,
+    pub pusher: &'static str,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: watch `{}` hit by {}: ", cyclize(self.cycle), self.watch, self.pusher)?;
+        match self.addr {
+            Some(addr) => write!(f, "[{}] = {}", addr, self.value),
+            None => write!(f, "push {}", self.value),
+        }
+    }
+}
+
+type Matcher<T> = Box<dyn Fn(&T) -> Option<i64>>;
+
+pub struct Watch<T> {
+    spec: WatchSpec,
+    matcher: Matcher<T>,
+}
+
+impl<T> Watch<T> {
+    pub fn check(&self, addr: Option<usize>, data: &T, cycle: usize, pusher: &'static str) -> Option<WatchHit> {
+        if self.spec.addr.is_some() && self.spec.addr != addr {
+            return None;
+        }
+        (self.matcher)(data).map(|value| WatchHit {
+            watch: self.spec.to_string(),
+            action: self.spec.action,
+            cycle,
+            addr,
+            value,
+            pusher,
+        })
+    }
+}
+
+impl<T: Sized + Default + Clone> Array<T> {
+    pub fn unwatch(&mut self, label: &str) -> bool {
+        let before = self.watches.len();
+        self.watches.retain(|w| w.spec.to_string() != label);
+        self.watches.len() != before
+    }
+    pub fn watch_labels(&self) -> Vec<String> {
+        self.watches.iter().map(|w| w.spec.to_string()).collect()
+    }
+}
+
+impl<T: Sized + Default + Clone + ValueCastTo<i64>> Array<T> {
+    pub fn watch(&mut self, spec: WatchSpec) -> Result<(), String> {
+        if spec.addr.is_some_and(|addr| addr >= self.payload.len()) {
+            return Err(format!("{} is out of range", spec));
+        }
+        self.watches.push(Watch {
+            matcher: spec.matcher(),
+            spec,
+        });
+        Ok(())
+    }
+}
+
+impl<T: Sized> FIFO<T> {
+    pub fn unwatch(&mut self, label: &str) -> bool {
+        let before = self.watches.len();
+        self.watches.retain(|w| w.spec.to_string() != label);
+        self.watches.len() != before
+    }
+
+    pub fn watch_labels(&self) -> Vec<String> {
+        self.watches.iter().map(|w| w.spec.to_string()).collect()
+    }
+}
+
+impl<T: Sized + ValueCastTo<i64>> FIFO<T> {
+    pub fn watch(&mut self, spec: WatchSpec) -> Result<(), String> {
+        if spec.addr.is_some() {
+            return Err(format!("{} is a FIFO and has no addresses", spec.target));
+        }
+        self.watches.push(Watch {
+            matcher: spec.matcher(),
+            spec,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::runtime::ArrayWrite;
+
+    #[test]
+    fn watch_spec() {
+        let spec = WatchSpec::parse("array_2aa85[15] >= -3 break", WatchAction::Log).unwrap();
+        assert_eq!(spec.target, "array_2aa85");
+        assert_eq!(spec.addr, Some(15));
+        assert_eq!(spec.cond, Some((CmpOp::Ge, -3)));
+        assert_eq!(spec.action, WatchAction::Break);
+        assert_eq!(spec.to_string(), "array_2aa85[15] >= -3 break");
+
+        let spec = WatchSpec::parse("  rdata  ", WatchAction::Break).unwrap();
+        assert_eq!((spec.addr, spec.cond, spec.action), (None, None, WatchAction::Break));
+        assert_eq!(spec.to_string(), "rdata break");
+        let spec = WatchSpec::parse("rdata log", WatchAction::Break).unwrap();
+        assert_eq!((spec.cond, spec.action), (None, WatchAction::Log));
+    }
+
+    #[test]
+    fn watch_spec_errors() {
+        let err = |s: &str| WatchSpec::parse(s, WatchAction::Log).unwrap_err();
+        assert_eq!(err(""), "empty watch");
+        assert_eq!(err("a[x]"), "invalid address in `a[x]`");
+        assert_eq!(err("a[3"), "invalid address in `a[3`");
+        assert_eq!(err("a[-1]"), "invalid address in `a[-1]`");
+        assert_eq!(err("a == ten"), "invalid value `ten`");
+        assert_eq!(err("a =~ 3"), "unexpected `=~ 3`");
+        assert_eq!(err("a == 3 log break"), "unexpected `log break`");
+        // an operator needs a value
+        assert_eq!(err("a <"), "unexpected `<`");
+    }
+
+    #[test]
+    fn watch_hits() {
+        let mut array = Array::<u16>::new(4);
+        assert!(array.watch(WatchSpec::parse("a[4]", WatchAction::Log).unwrap()).is_err());
+        array.watch(WatchSpec::parse("a[1] > 5", WatchAction::Log).unwrap()).unwrap();
+        array.write.push(ArrayWrite::new(1, 1, 5, "m"));
+        array.write.push(ArrayWrite::new(2, 1, 6, "m"));
+        array.write.push(ArrayWrite::new(3, 2, 9, "m"));
+        for cycle in 1..4 {
+            array.tick(cycle);
+        }
+        let hits: Vec<_> = array.hits.iter().map(|h| (h.cycle, h.addr, h.value)).collect();
+        assert_eq!(hits, vec![(2, Some(1), 6)]);
+        assert!(array.unwatch("a[1] > 5 log"));
+        assert!(!array.unwatch("a[1] > 5 log"));
+
+        let mut fifo = FIFO::<u16>::new();
+        assert!(fifo.watch(WatchSpec::parse("f[0]", WatchAction::Log).unwrap()).is_err());
+    }
+}
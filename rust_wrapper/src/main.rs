@@ -1,45 +1,46 @@
-use libloading::{Library, Symbol};
+use memory_interface::{MemoryError, MemoryInterface, Request, RequestKind};
+use std::env;
 use std::error::Error;
-use std::ffi::CString;
-use std::ffi::{c_char, c_float, c_longlong, c_void};
-mod memory_interface;
-use memory_interface::{MemoryInterface, Request};
+use std::ffi::c_void;
 
+// the workspace's example, unless a config is given as the argument
+const DEFAULT_CONFIG: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../configs/example_config.yaml");
 
-pub struct counter {
+
+pub struct Counter {
     pub value: i64,
 }
 
-extern "C" fn rust_callback(req: *mut Request, ctx: *mut c_void) {
+fn rust_callback(req: &Request, ctx: *mut c_void) {
     unsafe {
-        let req = &*req;
-        let count: &mut counter = &mut *(ctx as *mut counter);
+        let count: &mut Counter = &mut *(ctx as *mut Counter);
         println!("Callback called! Addr = {:#x}, counter = {}, latency = {}", req.addr, count.value, req.depart - req.arrive);
         count.value += 1;
     }
 }
 
 fn main() -> Result<(), Box<dyn Error>>{
+    let config = env::args().nth(1).unwrap_or_else(|| DEFAULT_CONFIG.to_string());
     unsafe {
-        let lib = Library::new("/root/wrapper/rust_wrapper/src/libwrapper.so")?;
-        let mem = MemoryInterface::new(&lib)?;
-        mem.init("/root/wrapper/configs/example_config.yaml");
+        let mem = MemoryInterface::open_default()?;
+        mem.init(&config)?;
 
-        let mut count = counter { value: 0 };
+        let mut count = Counter { value: 0 };
         let count_ptr = &mut count as *mut _ as *mut std::ffi::c_void;
 
         for i in 0..100 {
             let addr = 0x1000 + i * 64;
-            let a = mem.send_request(addr, false, rust_callback, count_ptr);
-            if a {
-                 println!("Request sent for address {:#x}, success: {}", addr, a);
+            match mem.send_request(addr, RequestKind::Read, rust_callback, count_ptr) {
+                Ok(()) => println!("Request sent for address {:#x}", addr),
+                Err(MemoryError::QueueFull) => {}
+                Err(e) => return Err(e.into()),
             }
            
-            mem.frontend_tick();
-            mem.memory_tick();
+            mem.frontend_tick()?;
+            mem.memory_tick()?;
         }
 
-        mem.finish();
+        mem.finish()?;
     }
     println!("all good!");
     Ok(())